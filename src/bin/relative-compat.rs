@@ -0,0 +1,213 @@
+//! Check whether two builds of a binary can exchange
+//! [`Vtable`](relative::Vtable)s, and which vtables moved between them.
+//!
+//! ```text
+//! relative-compat [--build-id <uuid>] [--new-build-id <uuid>] [--remap <file>] <old> <new>
+//! ```
+//!
+//! Vtables are compared by the names they're given in the debug info, so both
+//! binaries need to have been built with it. With `--remap`, a bincode
+//! [`Remap`] translating the vtables of `<old>` is written to `<file>`.
+//!
+//! The build id a reference is serialized with is computed by the running
+//! binary, as [`BinaryIdentity::build_id`](relative::BinaryIdentity::build_id),
+//! rather than read from a note in the file, so this tool can't derive it.
+//! Instead the ids each build logs are given with `--build-id` and
+//! `--new-build-id`, and printed; `--remap` requires the former. Either way,
+//! two builds share a build id exactly when their files are identical.
+
+#![warn(
+	missing_copy_implementations,
+	missing_debug_implementations,
+	missing_docs,
+	trivial_casts,
+	trivial_numeric_casts,
+	unused_import_braces,
+	unused_qualifications,
+	unused_results,
+	clippy::pedantic
+)]
+
+use relative::{symbols::Image, Remap};
+use std::{
+	collections::{BTreeMap, BTreeSet}, env, fmt, fs, process
+};
+use uuid::Uuid;
+
+const USAGE: &str =
+	"usage: relative-compat [--build-id <uuid>] [--new-build-id <uuid>] [--remap <file>] <old> <new>";
+
+fn main() {
+	match run() {
+		Ok(true) => (),
+		Ok(false) => process::exit(1),
+		Err(err) => {
+			eprintln!("relative-compat: {err}");
+			process::exit(2);
+		}
+	}
+}
+
+/// Returns whether the builds can exchange vtables as is.
+fn run() -> Result<bool, String> {
+	let mut remap = None;
+	let mut build = None;
+	let mut new_build = None;
+	let mut paths = Vec::new();
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match &*arg {
+			"--remap" => remap = Some(args.next().ok_or(USAGE)?),
+			"--build-id" => build = Some(parse_build(args.next())?),
+			"--new-build-id" => new_build = Some(parse_build(args.next())?),
+			"-h" | "--help" => {
+				println!("{USAGE}");
+				return Ok(true);
+			}
+			_ => paths.push(arg),
+		}
+	}
+	let [old_path, new_path] = &*paths else {
+		return Err(USAGE.to_owned());
+	};
+	let remap = match (remap, build) {
+		(Some(remap), Some(build)) => Some((remap, build)),
+		(None, _) => None,
+		(Some(_), None) => return Err(USAGE.to_owned()),
+	};
+	let old_data = fs::read(old_path).map_err(|err| format!("{old_path}: {err}"))?;
+	let new_data = fs::read(new_path).map_err(|err| format!("{new_path}: {err}"))?;
+	let old = Image::parse(&old_data).map_err(|err| format!("{old_path}: {err}"))?;
+	let new = Image::parse(&new_data).map_err(|err| format!("{new_path}: {err}"))?;
+
+	println!("old:       {old_path}, base at {:#x}", old.base());
+	println!("new:       {new_path}, base at {:#x}", new.base());
+	let identical = old_data == new_data;
+	match (build, new_build) {
+		(Some(old), Some(new)) if (old == new) != identical => {
+			return Err(format!(
+				"build ids {old} and {new} don't match the files, which {}",
+				if identical { "are identical" } else { "differ" }
+			));
+		}
+		(Some(old), Some(_)) if identical => println!("build ids: identical, {old}"),
+		(Some(old), Some(new)) => println!("build ids: differ, {old} -> {new}"),
+		_ if identical => println!("build ids: identical, as the files are"),
+		_ => println!("build ids: differ, as the files differ"),
+	}
+	if old.base() == new.base() {
+		println!("base:      same address");
+	} else {
+		println!(
+			"base:      moved from {:#x} to {:#x}",
+			old.base(),
+			new.base()
+		);
+	}
+	let diff = Diff::new(&old, &new);
+	let debug_info = old.vtables().next().is_some() && new.vtables().next().is_some();
+	if debug_info {
+		diff.print();
+	} else {
+		println!("vtables:   unknown, as a binary has no debug info");
+	}
+
+	if let Some((path, build)) = remap {
+		let remap = Remap::between(build, old.vtables(), new.vtables());
+		let data = bincode::serialize(&remap).map_err(|err| err.to_string())?;
+		fs::write(&path, data).map_err(|err| format!("{path}: {err}"))?;
+		println!("remap:     {} vtables written to {path}", remap.len());
+	}
+
+	if identical {
+		println!("verdict:   compatible");
+	} else if !debug_info {
+		println!("verdict:   unknown, as a binary has no debug info");
+	} else if diff.removed.is_empty() {
+		println!("verdict:   compatible with a remapping table or named mode");
+	} else {
+		println!("verdict:   partially compatible with a remapping table or named mode");
+	}
+	Ok(identical)
+}
+
+fn parse_build(arg: Option<String>) -> Result<Uuid, String> {
+	let id = arg.ok_or(USAGE)?;
+	Uuid::parse_str(&id).map_err(|err| format!("{id}: {err}"))
+}
+
+/// The vtables that stayed, moved, or were added or removed between builds.
+#[derive(Debug)]
+struct Diff<'a> {
+	same: usize,
+	moved: Vec<(&'a str, BTreeSet<i64>, BTreeSet<i64>)>,
+	removed: Vec<&'a str>,
+	added: Vec<&'a str>,
+}
+impl<'a> Diff<'a> {
+	fn new(old: &'a Image, new: &'a Image) -> Self {
+		fn by_name(image: &Image) -> BTreeMap<&str, BTreeSet<i64>> {
+			let mut vtables = BTreeMap::<_, BTreeSet<_>>::new();
+			for (name, offset) in image.vtables() {
+				let _ = vtables.entry(name).or_default().insert(offset);
+			}
+			vtables
+		}
+		let mut new = by_name(new);
+		let mut diff = Self {
+			same: 0,
+			moved: Vec::new(),
+			removed: Vec::new(),
+			added: Vec::new(),
+		};
+		for (name, old) in by_name(old) {
+			match new.remove(name) {
+				Some(new) if new == old => diff.same += 1,
+				Some(new) => diff.moved.push((name, old, new)),
+				None => diff.removed.push(name),
+			}
+		}
+		diff.added = new.into_keys().collect();
+		diff
+	}
+
+	fn print(&self) {
+		println!(
+			"vtables:   {} at the same offset, {} moved, {} removed, {} added",
+			self.same,
+			self.moved.len(),
+			self.removed.len(),
+			self.added.len()
+		);
+		for (name, old, new) in &self.moved {
+			println!("moved:     {name} {} -> {}", Offsets(old), Offsets(new));
+		}
+		for name in &self.removed {
+			println!("removed:   {name}");
+		}
+	}
+}
+
+struct Signed(i64);
+impl fmt::Display for Signed {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.0 < 0 {
+			write!(f, "-{:#x}", self.0.unsigned_abs())
+		} else {
+			write!(f, "{:#x}", self.0)
+		}
+	}
+}
+
+struct Offsets<'a>(&'a BTreeSet<i64>);
+impl fmt::Display for Offsets<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, offset) in self.0.iter().enumerate() {
+			if i != 0 {
+				f.write_str(",")?;
+			}
+			write!(f, "{}", Signed(*offset))?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,158 @@
+//! Decode a serialized [`Vtable`](relative::Vtable) and find which vtable it
+//! refers to in an ELF binary.
+//!
+//! ```text
+//! relative-inspect [--bincode | --json] [--build-id <uuid>] <binary> [<file>]
+//! ```
+//!
+//! The serialized vtable is read from `<file>`, or stdin, and is taken to be
+//! JSON if it begins with `[` or `"`, and bincode otherwise. The build id of a
+//! binary mixes in the type ids of the process, so can't be derived from the
+//! file alone; pass the id logged by the process with `--build-id` to check
+//! against it.
+
+#![warn(
+	missing_copy_implementations,
+	missing_debug_implementations,
+	missing_docs,
+	trivial_casts,
+	trivial_numeric_casts,
+	unused_import_braces,
+	unused_qualifications,
+	unused_results,
+	clippy::pedantic
+)]
+
+use relative::symbols::{is_vtable_for, Encoded, Image};
+use std::{
+	env, fs, io::{self, Read}, process
+};
+use uuid::Uuid;
+
+const USAGE: &str =
+	"usage: relative-inspect [--bincode | --json] [--build-id <uuid>] <binary> [<file>]";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Format {
+	Bincode,
+	Json,
+}
+
+fn main() {
+	match run() {
+		Ok(true) => (),
+		Ok(false) => process::exit(1),
+		Err(err) => {
+			eprintln!("relative-inspect: {err}");
+			process::exit(2);
+		}
+	}
+}
+
+/// Returns whether the vtable was found and matched the binary.
+fn run() -> Result<bool, String> {
+	let mut format = None;
+	let mut build = None;
+	let mut paths = Vec::new();
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match &*arg {
+			"--bincode" => format = Some(Format::Bincode),
+			"--json" => format = Some(Format::Json),
+			"--build-id" => {
+				let id = args.next().ok_or(USAGE)?;
+				build = Some(Uuid::parse_str(&id).map_err(|err| format!("{id}: {err}"))?);
+			}
+			"-h" | "--help" => {
+				println!("{USAGE}");
+				return Ok(true);
+			}
+			_ => paths.push(arg),
+		}
+	}
+	let (binary, input) = match &*paths {
+		[binary] => (binary, None),
+		[binary, input] => (binary, Some(input)),
+		_ => return Err(USAGE.to_owned()),
+	};
+	let data = if let Some(input) = input {
+		fs::read(input).map_err(|err| format!("{input}: {err}"))?
+	} else {
+		let mut data = Vec::new();
+		let _ = io::stdin()
+			.read_to_end(&mut data)
+			.map_err(|err| format!("stdin: {err}"))?;
+		data
+	};
+	let format =
+		format.unwrap_or_else(
+			|| match data.iter().find(|byte| !byte.is_ascii_whitespace()) {
+				Some(b'[' | b'"') => Format::Json,
+				_ => Format::Bincode,
+			},
+		);
+	let encoded: Encoded = match format {
+		Format::Bincode => bincode::deserialize(&data).map_err(|err| err.to_string())?,
+		Format::Json => serde_json::from_slice(&data).map_err(|err| err.to_string())?,
+	};
+	let image = Image::open(binary).map_err(|err| format!("{binary}: {err}"))?;
+	Ok(report(&encoded, &image, binary, build))
+}
+
+/// Print what's known about `encoded`, returning whether the vtable was found
+/// and matched.
+fn report(encoded: &Encoded, image: &Image, binary: &str, build: Option<Uuid>) -> bool {
+	let mut ok = true;
+	match encoded.version {
+		Some(version) => println!("format:    {version}"),
+		None => println!("format:    0.2"),
+	}
+	print!("build id:  {}", encoded.build);
+	match build {
+		Some(build) if build == encoded.build => println!(" (matches)"),
+		Some(build) => {
+			println!(" (differs from {build})");
+			ok = false;
+		}
+		None => println!(),
+	}
+	println!("type id:   {:x}", encoded.type_id);
+	if let Some(type_name) = &encoded.type_name {
+		println!("type:      {type_name}");
+	}
+	if let Some(name) = &encoded.name {
+		println!("name:      {name}");
+	}
+	if encoded.offset < 0 {
+		println!("offset:    -{:x}", encoded.offset.unsigned_abs());
+	} else {
+		println!("offset:    {:x}", encoded.offset);
+	}
+	if encoded.mac.is_some() {
+		println!("mac:       present");
+	}
+	#[allow(clippy::cast_sign_loss)]
+	let address = image.base().wrapping_add(encoded.offset as u64);
+	if let Some(vtable) = image.vtable(encoded.offset) {
+		println!("vtable:    {vtable} at {address:#x}");
+		if let Some(type_name) = &encoded.type_name {
+			if !is_vtable_for(vtable, type_name) {
+				println!("warning:   not a vtable for {type_name}");
+				ok = false;
+			}
+		}
+	} else {
+		match image.symbol(encoded.offset) {
+			Some((symbol, 0)) => println!("vtable:    none, at {symbol} {address:#x}"),
+			Some((symbol, offset)) => {
+				println!("vtable:    none, within {symbol}+{offset:#x} at {address:#x}");
+			}
+			None if image.vtables().next().is_none() => {
+				println!("vtable:    unknown, as {binary} has no debug info, at {address:#x}");
+			}
+			None => println!("vtable:    none at {address:#x}"),
+		}
+		ok = false;
+	}
+	ok
+}
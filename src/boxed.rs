@@ -0,0 +1,272 @@
+//! Serializable `Box<dyn Trait>`.
+//!
+//! The concrete value is serialized through an erased serializer, alongside a
+//! [`Vtable`] for the trait object. The receiver deserializes it with the
+//! function registered for that vtable with
+//! [`register_boxed`](crate::register_boxed), rather than one named by the
+//! payload, such that a vtable can't be paired with data of a different type.
+//!
+//! # Example
+//! ```
+//! use relative::{boxed, register_boxed};
+//! use serde_derive::{Deserialize, Serialize};
+//!
+//! trait Shape: boxed::Serialize + boxed::Deserialize {
+//!     fn area(&self) -> f64;
+//! }
+//! #[derive(Serialize, Deserialize)]
+//! struct Square(f64);
+//! impl Shape for Square {
+//!     fn area(&self) -> f64 {
+//!         self.0 * self.0
+//!     }
+//! }
+//!
+//! register_boxed!(Square => dyn Shape);
+//!
+//! let shape: boxed::Box<dyn Shape> = boxed::Box::new(Box::new(Square(2.0)));
+//! let json = serde_json::to_string(&shape).unwrap();
+//! // send `json` to remote...
+//! let shape: boxed::Box<dyn Shape> = serde_json::from_str(&json).unwrap();
+//! assert_eq!(shape.area(), 4.0);
+//! ```
+
+use serde::{
+	de::{self, DeserializeOwned, DeserializeSeed, Deserializer, SeqAccess, Visitor}, ser::Serializer
+};
+use std::{
+	any::type_name, boxed, collections::BTreeMap, fmt, marker, mem::transmute, ops::{Deref, DerefMut}, ptr, sync::{Mutex, PoisonError}
+};
+
+use super::{type_id, Error, Vtable};
+
+type DeserializeFn = unsafe fn(
+	*mut (dyn erased_serde::Deserializer<'static> + 'static),
+) -> Result<*mut (), erased_serde::Error>;
+
+static DESERIALIZERS: Mutex<BTreeMap<(u128, isize), DeserializeFn>> = Mutex::new(BTreeMap::new());
+
+/// Types that can be serialized as part of a [`Box<dyn Trait>`](Box).
+///
+/// This is implemented for all types that implement [`serde::Serialize`], and
+/// should be used as a supertrait of `Trait`.
+pub trait Serialize {
+	#[doc(hidden)]
+	fn __serialize(&self) -> &dyn erased_serde::Serialize;
+}
+impl<T: serde::Serialize> Serialize for T {
+	#[inline(always)]
+	fn __serialize(&self) -> &dyn erased_serde::Serialize {
+		self
+	}
+}
+
+/// Types that can be deserialized as part of a [`Box<dyn Trait>`](Box), once
+/// [registered](crate::register_boxed).
+///
+/// This is implemented for all types that implement
+/// [`serde::de::DeserializeOwned`], and should be used as a supertrait of
+/// `Trait`.
+pub trait Deserialize {}
+impl<T: DeserializeOwned + 'static> Deserialize for T {}
+
+#[doc(hidden)]
+#[inline(always)]
+pub fn __deserialize_fn<T: DeserializeOwned + 'static>() -> DeserializeFn {
+	deserialize::<T>
+}
+
+/// Deserialize the concrete type of `vtable` with `deserialize`.
+///
+/// # Safety
+///
+/// `deserialize` must deserialize a `Box<U>`, where `vtable` is the vtable for
+/// `T` of `U`.
+#[doc(hidden)]
+pub unsafe fn __register<T: ?Sized + 'static>(vtable: Vtable<T>, deserialize: DeserializeFn) {
+	let _ = DESERIALIZERS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert((type_id::<T>(), vtable.0), deserialize);
+}
+
+/// The function registered to deserialize the concrete type of the vtable for
+/// `T` at `offset`.
+fn deserializer<T: ?Sized + 'static>(offset: isize) -> Result<DeserializeFn, Error> {
+	DESERIALIZERS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.get(&(type_id::<T>(), offset))
+		.copied()
+		.ok_or(Error::Unregistered {
+			offset: offset as i64,
+			expected_name: type_name::<T>(),
+		})
+}
+
+unsafe fn deserialize<T: DeserializeOwned>(
+	deserializer: *mut (dyn erased_serde::Deserializer<'static> + 'static),
+) -> Result<*mut (), erased_serde::Error> {
+	erased_serde::deserialize::<T>(&mut *deserializer)
+		.map(|value| boxed::Box::into_raw(boxed::Box::new(value)).cast())
+}
+
+/// A `Box<dyn Trait>` that can be serialized and deserialized, where `Trait`
+/// has [`Serialize`] and [`Deserialize`] as supertraits.
+///
+/// `T` must be a trait object type. Only concrete types that have been
+/// [registered](crate::register_boxed) for it are deserialized, and others
+/// are rejected with [`Error::Unregistered`].
+pub struct Box<T: ?Sized>(boxed::Box<T>);
+impl<T: ?Sized> Box<T> {
+	/// Wrap a `Box<dyn Trait>`.
+	#[inline(always)]
+	pub fn new(b: boxed::Box<T>) -> Self {
+		Self(b)
+	}
+	/// Unwrap back into a `Box<dyn Trait>`.
+	#[inline(always)]
+	pub fn into_inner(self) -> boxed::Box<T> {
+		self.0
+	}
+}
+impl<T: ?Sized> From<boxed::Box<T>> for Box<T> {
+	#[inline(always)]
+	fn from(b: boxed::Box<T>) -> Self {
+		Self(b)
+	}
+}
+impl<T: ?Sized> Deref for Box<T> {
+	type Target = T;
+	#[inline(always)]
+	fn deref(&self) -> &T {
+		&self.0
+	}
+}
+impl<T: ?Sized> DerefMut for Box<T> {
+	#[inline(always)]
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.0
+	}
+}
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Box<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		self.0.fmt(f)
+	}
+}
+/// `T` must be a trait object type, which is checked at compile time.
+///
+/// ```compile_fail
+/// use relative::boxed;
+///
+/// let _ = serde_json::to_string(&boxed::Box::new(Box::new(1_u32)));
+/// ```
+impl<T: ?Sized + Serialize + Deserialize + 'static> serde::Serialize for Box<T> {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let vtable = unsafe { Vtable::<T>::__from_ptr(ptr::from_ref(&*self.0)) };
+		serde::Serialize::serialize(&(vtable, self.0.__serialize()), serializer)
+	}
+}
+impl<'de, T: ?Sized + Serialize + Deserialize + 'static> serde::Deserialize<'de> for Box<T> {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserializer.deserialize_tuple(2, BoxVisitor(marker::PhantomData))
+	}
+}
+
+struct BoxVisitor<T: ?Sized>(marker::PhantomData<fn(T)>);
+impl<'de, T: ?Sized + 'static> Visitor<'de> for BoxVisitor<T> {
+	type Value = Box<T>;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a relative::boxed::Box<{}>", type_name::<T>())
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let vtable: Vtable<T> = seq
+			.next_element()?
+			.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+		let deserialize = deserializer::<T>(vtable.0).map_err(Error::into_de)?;
+		let data = seq
+			.next_element_seed(DeserializeFnSeed(deserialize))?
+			.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+		Ok(Box(unsafe { vtable.to_box(data) }))
+	}
+}
+
+struct DeserializeFnSeed(DeserializeFn);
+impl<'de> DeserializeSeed<'de> for DeserializeFnSeed {
+	type Value = *mut ();
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+		let deserializer: &mut dyn erased_serde::Deserializer<'de> = &mut deserializer;
+		// The concrete type is `DeserializeOwned`, so the lifetime is irrelevant
+		#[allow(clippy::transmute_ptr_to_ptr)]
+		let deserializer = unsafe {
+			transmute::<
+				*mut (dyn erased_serde::Deserializer<'de> + '_),
+				*mut (dyn erased_serde::Deserializer<'static> + 'static),
+			>(deserializer)
+		};
+		unsafe { (self.0)(deserializer) }.map_err(de::Error::custom)
+	}
+}
+
+/// Register the concrete types that a [`boxed::Box<dyn Trait>`](Box) can be
+/// deserialized as, along with their vtables as with
+/// [`register_vtable`](crate::register_vtable!).
+///
+/// ```
+/// use relative::{boxed, register_boxed};
+///
+/// trait Name: boxed::Serialize + boxed::Deserialize {
+///     fn name(&self) -> String;
+/// }
+/// impl Name for u8 {
+///     fn name(&self) -> String {
+///         self.to_string()
+///     }
+/// }
+/// impl Name for String {
+///     fn name(&self) -> String {
+///         self.clone()
+///     }
+/// }
+///
+/// register_boxed!(u8, String => dyn Name);
+/// let a: boxed::Box<dyn Name> = boxed::Box::new(Box::new(String::from("abc")));
+/// let a: boxed::Box<dyn Name> =
+///     bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap();
+/// assert_eq!(a.name(), "abc");
+/// ```
+#[macro_export]
+macro_rules! register_boxed {
+	($concrete:ty => dyn $($bounds:tt)+) => {{
+		$crate::register_vtable!($concrete => dyn $($bounds)+);
+		#[allow(unused_unsafe)]
+		unsafe {
+			$crate::boxed::__register(
+				$crate::vtable!($concrete => dyn $($bounds)+),
+				$crate::boxed::__deserialize_fn::<$concrete>(),
+			);
+		}
+	}};
+	($concrete:ty, $($rest:ty),+ => dyn $($bounds:tt)+) => {{
+		$crate::register_boxed!($concrete => dyn $($bounds)+);
+		$crate::register_boxed!($($rest),+ => dyn $($bounds)+);
+	}};
+}
@@ -0,0 +1,222 @@
+use serde::de;
+use std::{cell::RefCell, error, fmt};
+use uuid::Uuid;
+
+thread_local! {
+	static LAST: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// An error encountered deserializing a relative reference.
+///
+/// Deserializers only pass on the message of an error, so use
+/// [`Error::from_serde`] to get it back from e.g. a `bincode::Error` or
+/// `serde_json::Error`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+	/// The reference came from a different binary.
+	BuildMismatch {
+		/// The build id it was serialized by.
+		got: Uuid,
+		/// The build id of this binary.
+		expected: Uuid,
+	},
+	/// The peer of a [`Session`](crate::Session) is the same binary, but laid
+	/// out differently in memory.
+	LayoutMismatch {
+		/// The layout fingerprint of the peer.
+		got: u64,
+		/// The layout fingerprint of this binary.
+		expected: u64,
+	},
+	/// The reference was serialized as a different type.
+	TypeMismatch {
+		/// The id of the type it was serialized as.
+		got: u128,
+		/// The name of the type it was serialized as, if it was serialized
+		/// with the "type-names" feature or has been
+		/// [registered](crate::register_type).
+		got_name: Option<String>,
+		/// The name of the type it was deserialized as.
+		expected_name: &'static str,
+		/// The id of the type it was deserialized as.
+		expected_id: u128,
+	},
+	/// The index of a vtable is beyond the end of the
+	/// [`VtableTable`](crate::VtableTable) it's being decoded with.
+	IndexOutOfRange {
+		/// The index it was serialized with.
+		index: u32,
+		/// The number of vtables in the table.
+		len: usize,
+	},
+	/// The reference hasn't been [registered](crate::register_vtable()) for
+	/// the type it was deserialized as, in [strict](crate::set_strict) mode,
+	/// or its concrete type hasn't been [registered](crate::register_boxed)
+	/// for a [`boxed::Box`](crate::boxed::Box).
+	Unregistered {
+		/// The offset it was serialized with.
+		offset: i64,
+		/// The name of the type it was deserialized as.
+		expected_name: &'static str,
+	},
+	/// The reference came from a different build in
+	/// [named](crate::set_named) mode, but no vtable is registered under its
+	/// name for the type it was deserialized as.
+	UnknownName {
+		/// The name it was serialized with.
+		name: String,
+		/// The name of the type it was deserialized as.
+		expected_name: &'static str,
+	},
+	/// The reference came from a build with a [registered](crate::register_remap)
+	/// remapping table, which doesn't translate its offset.
+	Unmapped {
+		/// The build it came from.
+		build: Uuid,
+		/// The offset it was serialized with.
+		offset: i64,
+	},
+	/// The reference wasn't authenticated by a valid MAC, while a key is set
+	/// with the "mac" feature.
+	Unauthenticated,
+	/// The reference doesn't land inside a suitable segment of the loaded
+	/// image of this binary, or is misaligned.
+	OutsideImage {
+		/// The offset it was serialized with, or `None` if it was serialized
+		/// without one, as a reference to a zero-sized value, but isn't.
+		offset: Option<i64>,
+	},
+	/// The bytes a [`Static`](crate::Static) refers to aren't a valid value of
+	/// the type it was deserialized as, i.e. a `str` that isn't UTF-8.
+	InvalidValue {
+		/// The offset it was serialized with.
+		offset: i64,
+		/// The name of the type it was deserialized as.
+		expected_name: &'static str,
+	},
+	/// The offset doesn't fit in this platform's pointer width.
+	OffsetOutOfRange {
+		/// The offset it was serialized with.
+		offset: i64,
+	},
+	/// The string form of the reference couldn't be parsed.
+	Malformed {
+		/// The string it was parsed from.
+		input: String,
+	},
+	/// The reference was serialized in a format version other than
+	/// [`FORMAT_VERSION`](crate::FORMAT_VERSION), or with features this version
+	/// doesn't support.
+	UnsupportedFormat {
+		/// The format version it was serialized in.
+		version: u8,
+		/// The flags it was serialized with.
+		flags: u8,
+	},
+}
+impl Error {
+	/// Get back the `Error` that caused a deserialization error, if any.
+	///
+	/// This must be called on the same thread that deserialization happened
+	/// on.
+	///
+	/// ```
+	/// use relative::{vtable, Error, Vtable};
+	/// use std::fmt::{Debug, Display};
+	///
+	/// let a = bincode::serialize(&vtable!(u8 => dyn Display)).unwrap();
+	/// let err = bincode::deserialize::<Vtable<dyn Debug>>(&a).unwrap_err();
+	/// assert!(matches!(Error::from_serde(&err), Some(Error::TypeMismatch { .. })));
+	/// ```
+	pub fn from_serde<E: fmt::Display + ?Sized>(err: &E) -> Option<Self> {
+		LAST.with(|last| {
+			last.borrow()
+				.clone()
+				.filter(|last| err.to_string().contains(&last.to_string()))
+		})
+	}
+	pub(crate) fn into_de<E: de::Error>(self) -> E {
+		let err = E::custom(&self);
+		LAST.with(|last| *last.borrow_mut() = Some(self));
+		err
+	}
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::BuildMismatch { got, expected } => write!(
+				f,
+				"relative reference came from a different binary {got}, expected {expected}"
+			),
+			Self::LayoutMismatch { got, expected } => write!(
+				f,
+				"relative peer has a different layout {got:#018x}, expected {expected:#018x}"
+			),
+			Self::TypeMismatch {
+				got,
+				got_name,
+				expected_name,
+				expected_id,
+			} => write!(
+				f,
+				"relative reference to wrong type {}:{got}, expected {expected_name}:{expected_id}",
+				got_name.as_deref().unwrap_or("???")
+			),
+			Self::IndexOutOfRange { index, len } => write!(
+				f,
+				"relative vtable index {index} out of range for table of length {len}"
+			),
+			Self::Unregistered {
+				offset,
+				expected_name,
+			} => write!(
+				f,
+				"relative reference at offset {offset} not registered for {expected_name}"
+			),
+			Self::UnknownName {
+				name,
+				expected_name,
+			} => write!(
+				f,
+				"relative reference to vtable {name} not registered for {expected_name}"
+			),
+			Self::Unmapped { build, offset } => write!(
+				f,
+				"relative reference offset {offset} from build {build} not in its remapping table"
+			),
+			Self::Unauthenticated => f.write_str("relative reference failed authentication"),
+			Self::OutsideImage {
+				offset: Some(offset),
+			} => write!(
+				f,
+				"relative reference offset {offset} lands outside the loaded image"
+			),
+			Self::OutsideImage { offset: None } => write!(
+				f,
+				"relative reference without an offset is to a non-zero-sized value"
+			),
+			Self::InvalidValue {
+				offset,
+				expected_name,
+			} => write!(
+				f,
+				"relative reference offset {offset} isn't to a valid {expected_name}"
+			),
+			Self::OffsetOutOfRange { offset } => write!(
+				f,
+				"relative reference offset {offset} doesn't fit in a {}-bit pointer",
+				usize::BITS
+			),
+			Self::Malformed { input } => write!(
+				f,
+				"malformed relative reference {input:?}, expected <build-id>:<type-id>:<offset>"
+			),
+			Self::UnsupportedFormat { version, flags } => write!(
+				f,
+				"relative reference in unsupported format version {version} flags {flags:#04x}, expected version {} or the untagged 0.2 layout",
+				crate::FORMAT_VERSION
+			),
+		}
+	}
+}
+impl error::Error for Error {}
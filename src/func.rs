@@ -0,0 +1,189 @@
+use super::{image, mac::Message, registry, Error, Offset};
+use serde::{
+	de::{Deserialize, Deserializer}, ser::{Serialize, Serializer}
+};
+use std::{any::type_name, cmp, fmt, hash, marker, mem::transmute};
+
+#[doc(hidden)]
+#[used]
+#[no_mangle]
+pub static RELATIVE_FUNC_BASE: extern "C" fn() = func_base;
+
+#[inline(never)]
+extern "C" fn func_base() {}
+
+/// Function pointer types, i.e. `fn(A) -> B`, `unsafe extern "C" fn(A) -> B`
+/// and so on, that can be wrapped in a [`Func`].
+///
+/// This is implemented for safe and unsafe, Rust and C ABI function pointers
+/// with up to 12 arguments. It is sealed and cannot be implemented outside of
+/// this crate.
+///
+/// It isn't implemented for higher-ranked function pointers, i.e. those taking
+/// references with an elided lifetime, like `fn(&str) -> usize`, as their
+/// impls would overlap with those above. A function taking `&'static`
+/// references, like `fn(&'static str) -> usize`, can be wrapped instead, though
+/// it can then only be called with such references.
+///
+/// ```compile_fail
+/// use relative::Func;
+///
+/// let _ = unsafe { Func::<fn(&str) -> usize>::from(str::len) };
+/// ```
+pub trait FnPtr: Copy + 'static + sealed::Sealed {
+	#[doc(hidden)]
+	fn addr(self) -> usize;
+	#[doc(hidden)]
+	unsafe fn from_addr(addr: usize) -> Self;
+}
+
+mod sealed {
+	pub trait Sealed {}
+}
+
+macro_rules! fn_ptr {
+	($($arg:ident)*) => {
+		fn_ptr!(@impl fn($($arg),*) -> R; $($arg)*);
+		fn_ptr!(@impl unsafe fn($($arg),*) -> R; $($arg)*);
+		fn_ptr!(@impl extern "C" fn($($arg),*) -> R; $($arg)*);
+		fn_ptr!(@impl unsafe extern "C" fn($($arg),*) -> R; $($arg)*);
+	};
+	(@impl $f:ty; $($arg:ident)*) => {
+		impl<$($arg: 'static,)* R: 'static> sealed::Sealed for $f {}
+		impl<$($arg: 'static,)* R: 'static> FnPtr for $f {
+			#[inline(always)]
+			fn addr(self) -> usize {
+				self as usize
+			}
+			#[inline(always)]
+			unsafe fn from_addr(addr: usize) -> Self {
+				transmute::<usize, Self>(addr)
+			}
+		}
+	};
+}
+fn_ptr!();
+fn_ptr!(A);
+fn_ptr!(A B);
+fn_ptr!(A B C);
+fn_ptr!(A B C D);
+fn_ptr!(A B C D E);
+fn_ptr!(A B C D E F);
+fn_ptr!(A B C D E F G);
+fn_ptr!(A B C D E F G H);
+fn_ptr!(A B C D E F G H I);
+fn_ptr!(A B C D E F G H I J);
+fn_ptr!(A B C D E F G H I J K);
+fn_ptr!(A B C D E F G H I J K L);
+
+/// Wraps function pointers such that they can be safely sent between other
+/// processes running the same binary.
+///
+/// For pointers into the segment that houses the code, typically the text
+/// segment.
+///
+/// The base used is the address of a function:
+/// ```ignore
+/// #[used]
+/// #[no_mangle]
+/// pub static RELATIVE_FUNC_BASE: extern "C" fn() = func_base;
+///
+/// let base = RELATIVE_FUNC_BASE as usize;
+/// ```
+pub struct Func<F>(pub(crate) isize, marker::PhantomData<fn() -> F>);
+impl<F: FnPtr> Func<F> {
+	#[inline(always)]
+	fn new(p: isize) -> Self {
+		Self(p, marker::PhantomData)
+	}
+	/// Create a `Func<F>` from a function pointer.
+	///
+	/// # Safety
+	///
+	/// This is unsafe as it is up to the user to ensure the function lies
+	/// within the binary, rather than in a dynamically loaded library.
+	///
+	/// i.e. the pointer needs to be positioned the same relative to the base in
+	/// every invocation, through e.g. being in the same segment, or the binary
+	/// being statically linked.
+	#[inline(always)]
+	pub unsafe fn from(f: F) -> Self {
+		let base = RELATIVE_FUNC_BASE as usize;
+		Self::new(super::displacement(base, f.addr()))
+	}
+	/// Get back the function pointer from a `Func<F>`.
+	///
+	/// # Safety
+	///
+	/// `self` must have been created by [`Func::from`], or deserialized from
+	/// one that was. Deserialization only checks that the offset lands inside
+	/// an executable segment, rather than at the start of a function of type
+	/// `F`, so a `Func` from a less trusted peer must have been
+	/// [registered](crate::register_func) and deserialized in
+	/// [strict](crate::set_strict) mode.
+	#[inline(always)]
+	pub unsafe fn to(&self) -> F {
+		let base = RELATIVE_FUNC_BASE as usize;
+		F::from_addr(base.wrapping_add_signed(self.0))
+	}
+}
+impl<F> Clone for Func<F> {
+	#[inline(always)]
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<F> Copy for Func<F> {}
+impl<F> PartialEq for Func<F> {
+	#[inline(always)]
+	fn eq(&self, other: &Self) -> bool {
+		self.0 == other.0
+	}
+}
+impl<F> Eq for Func<F> {}
+impl<F> hash::Hash for Func<F> {
+	#[inline(always)]
+	fn hash<H: hash::Hasher>(&self, state: &mut H) {
+		self.0.hash(state);
+	}
+}
+impl<F> PartialOrd for Func<F> {
+	#[inline(always)]
+	fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl<F> Ord for Func<F> {
+	#[inline(always)]
+	fn cmp(&self, other: &Self) -> cmp::Ordering {
+		self.0.cmp(&other.0)
+	}
+}
+impl<F> fmt::Debug for Func<F> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		f.debug_struct("Func")
+			.field(type_name::<F>(), &self.0)
+			.finish()
+	}
+}
+impl<F: FnPtr> Serialize for Func<F> {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		super::serialize::<F, _, _>(&Offset(self.0), Message::write, serializer)
+	}
+}
+impl<'de, F: FnPtr> Deserialize<'de> for Func<F> {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let Offset(offset) = super::deserialize::<F, _, _>(deserializer, Message::write)?;
+		image::check(offset, 1, 1, image::Kind::Executable).map_err(Error::into_de)?;
+		registry::check_func::<F>(offset).map_err(Error::into_de)?;
+		Ok(Self::new(offset))
+	}
+}
@@ -0,0 +1,401 @@
+//! Validation that deserialized references land inside the loaded image of
+//! this binary.
+//!
+//! On Linux the program headers of the loaded object containing the bases are
+//! found with `dl_iterate_phdr`. A vtable must then lie within a read-only
+//! segment, which includes the RELRO region that vtables are placed in when
+//! they need relocating; a function within an executable segment; and other
+//! statics within any loaded segment. If the object can't be found, every
+//! reference is rejected. Elsewhere, no validation is done.
+//!
+//! The same program headers are used by [`self_check`] to verify that vtables
+//! lie in the same loaded object as the base.
+
+use std::{fmt, ops::Range, sync::OnceLock};
+
+use super::{func::RELATIVE_FUNC_BASE, registry, vtable_base, Error};
+
+/// The kind of segment a reference must lie within.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Kind {
+	ReadOnly,
+	Executable,
+	Any,
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+	use std::{
+		convert::TryFrom, os::raw::{c_int, c_void}, slice
+	};
+
+	use super::{Object, Segment};
+
+	/// The loaded objects of this process, in the order `dl_iterate_phdr`
+	/// visits them, which begins with the main program.
+	#[allow(clippy::unnecessary_wraps)]
+	pub(super) fn objects() -> Option<Vec<Object>> {
+		unsafe extern "C" fn callback(
+			info: *mut libc::dl_phdr_info, _size: usize, data: *mut c_void,
+		) -> c_int {
+			let objects = &mut *data.cast::<Vec<Object>>();
+			let info = &*info;
+			let phdrs = slice::from_raw_parts(info.dlpi_phdr, usize::from(info.dlpi_phnum));
+			let Ok(bias) = usize::try_from(info.dlpi_addr) else {
+				return 1;
+			};
+			objects.push(Object {
+				main: objects.is_empty(),
+				bias,
+				interp: phdrs.iter().any(|phdr| phdr.p_type == libc::PT_INTERP),
+				segments: phdrs
+					.iter()
+					.filter(|phdr| {
+						phdr.p_type == libc::PT_LOAD || phdr.p_type == libc::PT_GNU_RELRO
+					})
+					.filter_map(|phdr| {
+						let start = bias.wrapping_add(usize::try_from(phdr.p_vaddr).ok()?);
+						Some(Segment {
+							range: start..start.checked_add(usize::try_from(phdr.p_memsz).ok()?)?,
+							load: phdr.p_type == libc::PT_LOAD,
+							writable: phdr.p_type == libc::PT_LOAD
+								&& phdr.p_flags & libc::PF_W != 0,
+							executable: phdr.p_flags & libc::PF_X != 0,
+						})
+					})
+					.collect(),
+			});
+			0
+		}
+		let mut objects = Vec::new();
+		let _ = unsafe {
+			libc::dl_iterate_phdr(
+				Some(callback),
+				std::ptr::from_mut(&mut objects).cast::<c_void>(),
+			)
+		};
+		Some(objects)
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+	use super::Object;
+
+	pub(super) fn objects() -> Option<Vec<Object>> {
+		None
+	}
+}
+
+/// An object loaded into this process, such as the executable or a shared
+/// library.
+#[derive(Debug)]
+struct Object {
+	main: bool,
+	bias: usize,
+	interp: bool,
+	segments: Vec<Segment>,
+}
+
+/// A loadable segment of an [`Object`], or its RELRO region.
+#[derive(Debug)]
+struct Segment {
+	range: Range<usize>,
+	load: bool,
+	writable: bool,
+	executable: bool,
+}
+
+/// Whether `len` bytes at `addr` lie within a segment of the given kind of
+/// the loaded object containing the base, or `true` if loaded objects can't be
+/// inspected on this platform. If the base isn't in any of them, nothing does.
+fn contains(addr: usize, len: usize, kind: Kind) -> bool {
+	static IMAGE: OnceLock<Option<Vec<Segment>>> = OnceLock::new();
+	let image = IMAGE.get_or_init(|| {
+		let mut objects = imp::objects()?;
+		Some(
+			locate(&objects, vtable_base())
+				.map_or_else(Vec::new, |(object, _)| objects.swap_remove(object).segments),
+		)
+	});
+	let Some(segments) = image else {
+		return true;
+	};
+	let Some(end) = addr.checked_add(len) else {
+		return false;
+	};
+	segments.iter().any(|segment| {
+		let kind = match kind {
+			Kind::ReadOnly => !segment.writable,
+			Kind::Executable => segment.executable,
+			Kind::Any => true,
+		};
+		kind && segment.range.start <= addr && end <= segment.range.end
+	})
+}
+
+/// Check that `len` bytes at `offset` from the base lie within a segment of
+/// the given kind, and are aligned to `align`.
+pub(crate) fn check(offset: isize, len: usize, align: usize, kind: Kind) -> Result<(), Error> {
+	let base = if kind == Kind::Executable {
+		RELATIVE_FUNC_BASE as usize
+	} else {
+		vtable_base()
+	};
+	let addr = base.wrapping_add_signed(offset);
+	if addr % align == 0 && contains(addr, len, kind) {
+		Ok(())
+	} else {
+		Err(Error::OutsideImage {
+			offset: Some(offset as i64),
+		})
+	}
+}
+
+/// Check that a vtable at `offset` from the base lies within a read-only
+/// segment.
+pub(crate) fn check_vtable(offset: isize) -> Result<(), Error> {
+	check(
+		offset,
+		3 * size_of::<usize>(),
+		align_of::<usize>(),
+		Kind::ReadOnly,
+	)
+}
+
+/// The index of the loaded object containing `addr`, and of its loadable
+/// segment containing it.
+fn locate(objects: &[Object], addr: usize) -> Option<(usize, usize)> {
+	objects.iter().enumerate().find_map(|(i, object)| {
+		let segment = object
+			.segments
+			.iter()
+			.position(|segment| segment.load && segment.range.contains(&addr))?;
+		Some((i, segment))
+	})
+}
+
+/// How the object containing the base was linked.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Linkage {
+	/// A position-dependent executable, loaded at a fixed address.
+	NonPie,
+	/// A position-independent executable, relocated as a whole by the dynamic
+	/// loader.
+	Pie,
+	/// A statically linked position-independent executable, which relocates
+	/// itself as a whole.
+	StaticPie,
+	/// A shared library, rather than the executable.
+	SharedLibrary,
+	/// The linkage couldn't be determined, as on platforms other than Linux.
+	Unknown,
+}
+impl fmt::Display for Linkage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::NonPie => "non-PIE executable",
+			Self::Pie => "PIE executable",
+			Self::StaticPie => "static-pie executable",
+			Self::SharedLibrary => "shared library",
+			Self::Unknown => "unknown linkage",
+		})
+	}
+}
+
+/// Where a sample vtable lies relative to the vtable of
+/// `RELATIVE_VTABLE_BASE`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Placement {
+	/// In the same segment of the same loaded object.
+	SameSegment,
+	/// In a different segment of the same loaded object. Segments are loaded
+	/// at fixed positions relative to each other, so its offset is still
+	/// stable.
+	SameObject,
+	/// In a different loaded object, so its offset varies between
+	/// invocations.
+	OtherObject,
+	/// Not in any loaded object.
+	Unmapped,
+	/// Couldn't be determined, as on platforms other than Linux.
+	Unknown,
+}
+
+/// The outcome of [`self_check`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+	/// The offsets of vtables from the base are stable between invocations.
+	Ok,
+	/// The offsets of vtables from the base may vary between invocations, so
+	/// `Vtable`s from other processes may not be resolved correctly.
+	Failed,
+	/// The offsets couldn't be verified, as on platforms other than Linux.
+	Unverified,
+}
+
+/// A report of whether the assumptions this crate relies on hold in this
+/// process, as returned by [`self_check`].
+///
+/// Its `Display` form is a one-line verdict suitable for logging.
+#[derive(Clone, Debug)]
+pub struct SelfCheck {
+	linkage: Linkage,
+	samples: Vec<(String, Placement)>,
+	verdict: Verdict,
+}
+impl SelfCheck {
+	/// How the object containing the base was linked.
+	pub fn linkage(&self) -> Linkage {
+		self.linkage
+	}
+	/// The sample vtables checked, with where they lie relative to the base.
+	pub fn samples(&self) -> &[(String, Placement)] {
+		&self.samples
+	}
+	/// The verdict.
+	pub fn verdict(&self) -> Verdict {
+		self.verdict
+	}
+	/// Whether the verdict is [`Verdict::Ok`].
+	pub fn is_ok(&self) -> bool {
+		self.verdict == Verdict::Ok
+	}
+}
+impl fmt::Display for SelfCheck {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let count = |placement| self.samples.iter().filter(|(_, p)| *p == placement).count();
+		match self.verdict {
+			Verdict::Ok => {
+				write!(
+					f,
+					"ok: {}, with {} of {} sample vtables in the same segment as the base",
+					self.linkage,
+					count(Placement::SameSegment),
+					self.samples.len()
+				)?;
+				if count(Placement::SameObject) != 0 {
+					f.write_str(" and the rest in the same object")?;
+				}
+				Ok(())
+			}
+			Verdict::Unverified => write!(
+				f,
+				"unverified: loaded objects can't be inspected on this platform"
+			),
+			Verdict::Failed if self.linkage == Linkage::Unknown => {
+				write!(f, "failed: the base vtable isn't in any loaded object")
+			}
+			Verdict::Failed => {
+				write!(f, "failed: {}, with vtables", self.linkage)?;
+				for (name, placement) in &self.samples {
+					match placement {
+						Placement::OtherObject => write!(f, " {name} in another object")?,
+						Placement::Unmapped => write!(f, " {name} in no object")?,
+						_ => (),
+					}
+				}
+				f.write_str(", so their offsets from the base aren't stable")
+			}
+		}
+	}
+}
+
+/// Check that the assumptions this crate relies on hold in this process.
+///
+/// A `Vtable` is only resolved correctly by another process if vtables keep
+/// the same offset from the vtable of `RELATIVE_VTABLE_BASE` in every
+/// invocation. This checks that it, a set of sample vtables, and every vtable
+/// [registered](crate::register_vtable()) so far, lie in the same loaded
+/// object, and reports how that object was linked. Registered vtables are
+/// named as they were [registered](crate::register_vtable_as), or otherwise by
+/// their offset.
+///
+/// ```
+/// let check = relative::self_check();
+/// println!("relative: {}", check);
+/// # #[cfg(target_os = "linux")]
+/// assert!(check.is_ok());
+/// ```
+pub fn self_check() -> SelfCheck {
+	use std::{
+		error, fmt::{Debug, Display}, io
+	};
+	let samples: [(&'static str, &'static ()); 4] = [
+		(
+			"<u8 as core::fmt::Display>",
+			crate::vtable!(u8 => dyn Display).to(),
+		),
+		(
+			"<alloc::string::String as core::fmt::Debug>",
+			crate::vtable!(String => dyn Debug).to(),
+		),
+		(
+			"<std::io::error::Error as core::error::Error>",
+			crate::vtable!(io::Error => dyn error::Error).to(),
+		),
+		(
+			"<alloc::boxed::Box<dyn core::any::Any> as core::fmt::Debug>",
+			crate::vtable!(Box<dyn std::any::Any> => dyn Debug).to(),
+		),
+	];
+	let samples = samples
+		.iter()
+		.map(|&(name, vtable)| (name.to_owned(), std::ptr::from_ref(vtable) as usize))
+		.chain(registry::vtables().into_iter().map(|(offset, name)| {
+			let name = name.map_or_else(
+				|| format!("registered vtable at offset {offset}"),
+				str::to_owned,
+			);
+			(name, vtable_base().wrapping_add_signed(offset))
+		}))
+		.collect::<Vec<_>>();
+	let Some(objects) = imp::objects() else {
+		return SelfCheck {
+			linkage: Linkage::Unknown,
+			samples: samples
+				.into_iter()
+				.map(|(name, _)| (name, Placement::Unknown))
+				.collect(),
+			verdict: Verdict::Unverified,
+		};
+	};
+	let base = locate(&objects, vtable_base());
+	let linkage = base.map_or(Linkage::Unknown, |(object, _)| {
+		let object = &objects[object];
+		if !object.main {
+			Linkage::SharedLibrary
+		} else if object.bias == 0 {
+			Linkage::NonPie
+		} else if object.interp {
+			Linkage::Pie
+		} else {
+			Linkage::StaticPie
+		}
+	});
+	let samples: Vec<_> = samples
+		.into_iter()
+		.map(|(name, addr)| {
+			let placement = match (base, locate(&objects, addr)) {
+				(Some(base), Some(sample)) if base == sample => Placement::SameSegment,
+				(Some((base, _)), Some((sample, _))) if base == sample => Placement::SameObject,
+				(_, Some(_)) => Placement::OtherObject,
+				(_, None) => Placement::Unmapped,
+			};
+			(name, placement)
+		})
+		.collect();
+	let verdict = if base.is_some()
+		&& samples.iter().all(|(_, placement)| {
+			matches!(placement, Placement::SameSegment | Placement::SameObject)
+		}) {
+		Verdict::Ok
+	} else {
+		Verdict::Failed
+	};
+	SelfCheck {
+		linkage,
+		samples,
+		verdict,
+	}
+}
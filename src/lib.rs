@@ -9,42 +9,59 @@
 //!
 //! It being the same binary is checked by serialising the
 //! [`build_id`](https://docs.rs/build_id) alongside the relative pointer, which
-//! is validated at deserialisation.
+//! is validated at deserialisation. On Linux, the pointer is also checked to
+//! land inside a suitable segment of the loaded binary, and [`self_check`]
+//! verifies at startup that vtables lie in the same loaded object as the base
+//! that offsets are taken from.
+//!
+//! [`Vtable`] wraps references to vtables, [`Func`] wraps function pointers,
+//! and [`Static`] and [`StaticDyn`] wrap references to statics. [`boxed::Box`]
+//! builds on `Vtable` to make `Box<dyn Trait>` serializable, for concrete
+//! types registered with [`register_boxed`].
+//!
+//! Peers that exchange and check a [`BinaryIdentity`] up front can send these
+//! within a [`Session`], where they serialize as just their offset. Batches in
+//! which the same vtables repeat can intern them with a [`VtableTable`].
+//!
+//! Payloads from less trusted peers can be restricted to vtables, functions
+//! and statics that have been [registered](register_vtable!) by enabling
+//! [strict](set_strict) mode. In [named](set_named) mode, registered vtables
+//! are serialized with their name too, such that a different build of the
+//! program can resolve them. Alternatively, a [`Remap`] generated offline
+//! from two builds translates the vtables of the older one. With the "mac"
+//! feature, references can be authenticated with a keyed MAC, such that a
+//! peer without the key can't forge them.
+//!
+//! With the "symbols" feature, [`symbols`] looks up which vtable an offset
+//! refers to in an ELF binary. The `relative-inspect` tool, built with the
+//! "cli" feature, uses it to decode a serialized `Vtable` for diagnosis, and
+//! `relative-compat` to compare the vtables of two builds before an upgrade.
 //!
 //! # Example
 //! ### Local process
 //! ```
-//! # #![feature(raw)]
 //! # use relative::*;
-//! use std::{fmt::Display, mem::transmute, raw::TraitObject};
+//! use std::fmt::Display;
 //!
-//! let mut x: Box<dyn Display> = Box::new("hello world");
-//! let x_ptr: *mut dyn Display = &mut *x;
-//! let x_ptr: TraitObject = unsafe { transmute(x_ptr) };
-//! let relative = unsafe { Vtable::<dyn Display>::from(&*x_ptr.vtable) };
+//! let x: Box<dyn Display> = Box::new("hello world");
+//! let relative = vtable!(val &*x => dyn Display);
 //! // send `relative` to remote...
 //! ```
 //! ### Remote process
 //! ```
-//! # #![feature(raw)]
 //! # use relative::*;
-//! # use std::{fmt::Display, mem::transmute, raw::TraitObject};
-//! # let mut x: Box<dyn Display> = Box::new("hello world");
-//! # let x_ptr: *mut dyn Display = &mut *x;
-//! # let x_ptr: TraitObject = unsafe { transmute(x_ptr) };
-//! # let relative = unsafe { Vtable::<dyn Display>::from(&*x_ptr.vtable) };
+//! # use std::fmt::Display;
+//! # let x: Box<dyn Display> = Box::new("hello world");
+//! # let relative = vtable!(val &*x => dyn Display);
 //! // receive `relative`
 //! let x: Box<&str> = Box::new("goodbye world");
-//! let x_ptr = Box::into_raw(x);
-//! let y_ptr = TraitObject { data: x_ptr.cast(), vtable: relative.to() as *const () as *mut () };
-//! let y_ptr: *mut dyn Display = unsafe { transmute(y_ptr) };
-//! let y: Box<dyn Display> = unsafe { Box::from_raw(y_ptr) };
+//! let y: Box<dyn Display> = unsafe { relative.to_box(Box::into_raw(x).cast()) };
 //! println!("{}", y);
 //! // prints "goodbye world"
 //! ```
 
 #![doc(html_root_url = "https://docs.rs/relative/0.2.2")]
-#![cfg_attr(feature = "nightly", feature(raw))]
+#![cfg_attr(feature = "nightly", feature(ptr_metadata, unsize))]
 #![warn(
 	missing_copy_implementations,
 	missing_debug_implementations,
@@ -63,19 +80,58 @@
 )]
 
 use serde::{
-	de::{self, Deserialize, Deserializer}, ser::{Serialize, Serializer}
+	de::{Deserialize, Deserializer}, ser::{Serialize, Serializer}
 };
 use std::{
-	any::{type_name, Any, TypeId}, cmp, fmt, hash, marker, mem::transmute
+	any::{type_name, Any, TypeId}, cmp, collections::BTreeMap, fmt, hash, marker, mem::transmute, rc::Rc, str, sync::{Arc, Mutex, PoisonError}
+};
+
+pub mod boxed;
+mod error;
+mod func;
+mod image;
+mod mac;
+mod registry;
+mod remap;
+mod session;
+mod statics;
+#[cfg(feature = "symbols")]
+pub mod symbols;
+mod table;
+mod wire;
+
+pub use error::Error;
+pub use func::{FnPtr, Func};
+pub use image::{self_check, Linkage, Placement, SelfCheck, Verdict};
+#[cfg(feature = "mac")]
+pub use mac::{clear_mac_key, set_mac_key};
+pub use registry::{
+	is_named, is_strict, register_func, register_static, register_static_dyn, register_vtable, register_vtable_as, set_named, set_strict
 };
-use uuid::Uuid;
+pub use remap::{register_remap, Remap};
+pub use session::{BinaryIdentity, Session};
+pub use statics::{Pod, Pointee, Static, StaticDyn};
+pub use table::VtableTable;
+
+pub use wire::FORMAT_VERSION;
+
+use wire::{deserialize, deserialize_offset, serialize, serialize_offset, Offset};
 
 #[doc(hidden)]
 #[used]
 #[no_mangle]
 pub static RELATIVE_VTABLE_BASE: &(dyn Any + Sync) = &();
 
-fn type_id<T: ?Sized + 'static>() -> u64 {
+/// The full 128 bits of `TypeId`. This relies on the layout of `TypeId`: the
+/// static size guarantee will catch it changing width, and the `type_id_repr`
+/// test pins the bits to those `TypeId`'s `Debug` impl prints.
+fn type_id<T: ?Sized + 'static>() -> u128 {
+	const _: () = assert!(size_of::<TypeId>() == size_of::<u128>());
+	unsafe { transmute::<TypeId, u128>(TypeId::of::<T>()) }
+}
+
+/// The hashed type id used by the 0.2 format.
+fn legacy_type_id<T: ?Sized + 'static>() -> u64 {
 	use std::hash::{Hash, Hasher};
 	let type_id = TypeId::of::<T>();
 	let mut hasher = std::collections::hash_map::DefaultHasher::new();
@@ -83,6 +139,62 @@ fn type_id<T: ?Sized + 'static>() -> u64 {
 	hasher.finish()
 }
 
+static TYPE_NAMES: Mutex<BTreeMap<u128, &str>> = Mutex::new(BTreeMap::new());
+
+/// Register `T`, such that its name can be looked up from the id that is
+/// serialized alongside references to it.
+///
+/// This is used to give the name of the received type in
+/// [`Error::TypeMismatch`], where the "type-names" feature isn't enabled.
+pub fn register_type<T: ?Sized + 'static>() {
+	let mut type_names = TYPE_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+	let _ = type_names.insert(type_id::<T>(), type_name::<T>());
+	let _ = type_names.insert(legacy_type_id::<T>().into(), type_name::<T>());
+}
+
+/// Look up the name of a type from its id, if it has been registered with
+/// [`register_type`].
+pub fn lookup_type_name(id: u128) -> Option<&'static str> {
+	TYPE_NAMES
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.get(&id)
+		.copied()
+}
+
+/// The address of the vtable of `RELATIVE_VTABLE_BASE`, which references into
+/// static memory are taken relative to.
+#[inline(always)]
+fn vtable_base() -> usize {
+	let base =
+		unsafe { transmute::<*const dyn Any, TraitObject>(RELATIVE_VTABLE_BASE) }.vtable as usize;
+	#[cfg(feature = "nightly")]
+	{
+		let check_base = unsafe {
+			transmute::<std::ptr::DynMetadata<dyn Any + Sync>, usize>(std::ptr::metadata(
+				RELATIVE_VTABLE_BASE,
+			))
+		};
+		assert_eq!(check_base, base);
+	}
+	base
+}
+
+/// The signed displacement of `addr` from `base`, such that references below
+/// the base have small negative offsets rather than ones close to `2^64`.
+#[inline(always)]
+#[allow(clippy::cast_possible_wrap)]
+fn displacement(base: usize, addr: usize) -> isize {
+	addr.wrapping_sub(base) as isize
+}
+
+/// Check that a deserialized vtable for `T` lands inside the loaded image and,
+/// in strict mode, has been registered.
+fn check_vtable<T: ?Sized + 'static>(offset: isize) -> Result<(), Error> {
+	image::check_vtable(offset)?;
+	registry::check::<T>(offset)
+}
+
 /// This is obviously a terrible no good hack to avoid requiring nightly.
 /// As well as the static size guarantee, it's correctness is asserted with the
 /// "nightly" feature, which should provide adequate warning in the event that
@@ -96,6 +208,23 @@ struct TraitObject {
 	vtable: *mut (),
 }
 
+/// The size of the concrete type of a trait object, read from its vtable.
+///
+/// Vtables begin with the drop glue, size and alignment of the concrete type.
+/// Like `TraitObject`, this layout is pretty baked into the compiler.
+#[inline(always)]
+fn vtable_size(vtable: &'static ()) -> usize {
+	let vtable: *const () = vtable;
+	unsafe { *vtable.cast::<usize>().add(1) }
+}
+
+/// The alignment of the concrete type of a trait object, read from its vtable.
+#[inline(always)]
+fn vtable_align(vtable: &'static ()) -> usize {
+	let vtable: *const () = vtable;
+	unsafe { *vtable.cast::<usize>().add(2) }
+}
+
 /// Wraps `&'static` references to vtables such that they can be safely sent
 /// between other processes running the same binary.
 ///
@@ -108,12 +237,15 @@ struct TraitObject {
 /// #[no_mangle]
 /// pub static RELATIVE_VTABLE_BASE: &(dyn Any + Sync) = &();
 ///
-/// let base = transmute::<*const dyn Any, std::raw::TraitObject>(RELATIVE_VTABLE_BASE).vtable as usize;
+/// let base = transmute::<DynMetadata<dyn Any>, usize>(ptr::metadata(RELATIVE_VTABLE_BASE));
 /// ```
-pub struct Vtable<T: ?Sized>(usize, marker::PhantomData<fn(T)>);
+///
+/// A `Vtable<dyn Trait>` can be safely created with the [`vtable`] macro, or
+/// with `Vtable::of` and `Vtable::of_val` with the "nightly" feature.
+pub struct Vtable<T: ?Sized>(isize, marker::PhantomData<fn(T)>);
 impl<T: ?Sized> Vtable<T> {
 	#[inline(always)]
-	fn new(p: usize) -> Self {
+	fn new(p: isize) -> Self {
 		Self(p, marker::PhantomData)
 	}
 	/// Create a `Vtable<T>` from a `&'static ()`.
@@ -128,41 +260,230 @@ impl<T: ?Sized> Vtable<T> {
 	/// being statically linked.
 	#[inline(always)]
 	pub unsafe fn from(ptr: &'static ()) -> Self {
-		let base = transmute::<*const dyn Any, TraitObject>(RELATIVE_VTABLE_BASE).vtable as usize;
-		#[cfg(feature = "nightly")]
-		{
-			let check_base =
-				transmute::<*const dyn Any, std::raw::TraitObject>(RELATIVE_VTABLE_BASE).vtable
-					as usize;
-			assert_eq!(check_base, base);
-		}
-		Self::new(
-			({
-				let ptr: *const () = ptr;
-				ptr
-			} as usize)
-				.wrapping_sub(base),
-		)
+		let ptr: *const () = ptr;
+		Self::new(displacement(vtable_base(), ptr as usize))
+	}
+	#[doc(hidden)]
+	#[inline(always)]
+	pub unsafe fn __from_ptr(ptr: *const T) -> Self {
+		const { assert!(size_of::<*const T>() == size_of::<TraitObject>()) };
+		let trait_object = std::mem::transmute_copy::<*const T, TraitObject>(&ptr);
+		Self::from(&*trait_object.vtable)
 	}
 	/// Get back a `&'static ()` from a `Vtable<T>`.
 	#[inline(always)]
 	pub fn to(&self) -> &'static () {
-		let base = unsafe { transmute::<*const dyn Any, TraitObject>(RELATIVE_VTABLE_BASE) }.vtable
-			as usize;
-		#[cfg(feature = "nightly")]
-		{
-			let check_base =
-				unsafe { transmute::<*const dyn Any, std::raw::TraitObject>(RELATIVE_VTABLE_BASE) }
-					.vtable as usize;
-			assert_eq!(check_base, base);
-		}
-		unsafe { &*(base.wrapping_add(self.0) as *const ()) }
+		unsafe { &*(vtable_base().wrapping_add_signed(self.0) as *const ()) }
+	}
+	/// The name of the vtable in the running binary, like
+	/// `<u8 as core::fmt::Display>::{vtable}`, from its debug info, or
+	/// otherwise the name of the symbol at its address, if there is one.
+	///
+	/// The binary is read and parsed on first use, which is slow, so this is
+	/// intended for diagnostics.
+	///
+	/// ```
+	/// use relative::vtable;
+	/// use std::fmt::Display;
+	///
+	/// // `None` if this binary wasn't built with debug info
+	/// if let Some(symbol) = vtable!(u8 => dyn Display).symbol() {
+	///     assert_eq!(symbol, "<u8 as core::fmt::Display>::{vtable}");
+	/// }
+	/// ```
+	#[cfg(feature = "symbols")]
+	pub fn symbol(&self) -> Option<&'static str> {
+		let image = symbols::Image::current()?;
+		image
+			.vtable(self.0 as i64)
+			.or_else(|| match image.symbol(self.0 as i64) {
+				Some((name, 0)) => Some(name),
+				_ => None,
+			})
+	}
+	/// Wrap the vtable such that its `Debug` form also includes its
+	/// [symbol](Self::symbol), which is as slow to look up.
+	///
+	/// ```
+	/// use relative::vtable;
+	/// use std::fmt::Display;
+	///
+	/// println!("{:?}", vtable!(u8 => dyn Display).with_symbol());
+	/// ```
+	#[cfg(feature = "symbols")]
+	pub fn with_symbol(&self) -> symbols::WithSymbol<T> {
+		symbols::WithSymbol(*self)
+	}
+}
+/// Reassembling trait objects from a `Vtable<dyn Trait>` and a pointer to the
+/// data.
+///
+/// A `Vtable<T>` is the vtable for `T` of some concrete type `U` if it was
+/// created by the [`vtable`] macro, `Vtable::of` or `Vtable::of_val`, or
+/// deserialized from one that was.
+impl<T: ?Sized> Vtable<T> {
+	/// Reassemble a `*mut dyn Trait`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`. `data` need
+	/// not be valid, but dereferencing the returned pointer requires it to
+	/// point to a valid `U`.
+	#[inline(always)]
+	pub unsafe fn to_raw(&self, data: *mut ()) -> *mut T {
+		const { assert!(size_of::<*mut T>() == size_of::<TraitObject>()) };
+		let vtable: *const () = self.to();
+		let trait_object = TraitObject {
+			data,
+			vtable: vtable.cast_mut(),
+		};
+		std::mem::transmute_copy::<TraitObject, *mut T>(&trait_object)
+	}
+	/// Reassemble a `&dyn Trait`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`, and `data`
+	/// must point to a valid `U` that lives for `'a` and isn't mutated for its
+	/// duration.
+	#[inline(always)]
+	pub unsafe fn to_ref<'a>(&self, data: *const ()) -> &'a T {
+		&*self.to_raw(data.cast_mut())
+	}
+	/// Reassemble a `Box<dyn Trait>`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`, and `data`
+	/// must have been returned by [`Box::<U>::into_raw`](Box::into_raw).
+	#[inline(always)]
+	pub unsafe fn to_box(&self, data: *mut ()) -> Box<T> {
+		Box::from_raw(self.to_raw(data))
+	}
+	/// Reassemble a `Rc<dyn Trait>`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`, and `data`
+	/// must have been returned by [`Rc::<U>::into_raw`](Rc::into_raw).
+	#[inline(always)]
+	pub unsafe fn to_rc(&self, data: *const ()) -> Rc<T> {
+		Rc::from_raw(self.to_raw(data.cast_mut()))
+	}
+	/// Reassemble an `Arc<dyn Trait>`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`, and `data`
+	/// must have been returned by [`Arc::<U>::into_raw`](Arc::into_raw).
+	#[inline(always)]
+	pub unsafe fn to_arc(&self, data: *const ()) -> Arc<T> {
+		Arc::from_raw(self.to_raw(data.cast_mut()))
+	}
+	/// Run the destructor of the concrete type `U` on the value at `data`,
+	/// as [`ptr::drop_in_place`](std::ptr::drop_in_place) does.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`, and `data`
+	/// must satisfy the requirements of `ptr::drop_in_place` for a `*mut U`.
+	#[inline(always)]
+	pub unsafe fn drop_in_place(&self, data: *mut ()) {
+		std::ptr::drop_in_place(self.to_raw(data));
+	}
+}
+/// Introspecting the concrete type from a `Vtable<dyn Trait>`, such that e.g.
+/// a suitably sized and aligned buffer can be allocated for it before its data
+/// arrives.
+///
+/// Every vtable starts with the same header, so with the "nightly" feature the
+/// size and alignment are read through a `DynMetadata<dyn Any>`. Without it,
+/// they're read from the second and third words of the vtable, where the
+/// compiler places them, which is asserted to be plausible.
+///
+/// These read the vtable, so as with [reassembling](Vtable::to_raw) a trait
+/// object, `self` must be the vtable for `T` of some concrete type `U`.
+///
+/// ```
+/// use relative::vtable;
+/// use std::{alloc::Layout, fmt::Debug};
+///
+/// let vtable = vtable!(String => dyn Debug);
+/// let (size, align) = unsafe { (vtable.size_of_concrete(), vtable.align_of_concrete()) };
+/// assert_eq!(Layout::from_size_align(size, align).unwrap(), Layout::new::<String>());
+/// ```
+impl<T: ?Sized> Vtable<T> {
+	/// The size of the concrete type `U`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`.
+	///
+	/// # Panics
+	///
+	/// Panics if the vtable doesn't have the expected layout.
+	#[inline(always)]
+	pub unsafe fn size_of_concrete(&self) -> usize {
+		self.layout().0
+	}
+	/// The alignment of the concrete type `U`.
+	///
+	/// # Safety
+	///
+	/// `self` must be the vtable for `T` of some concrete type `U`.
+	///
+	/// # Panics
+	///
+	/// Panics if the vtable doesn't have the expected layout.
+	#[inline(always)]
+	pub unsafe fn align_of_concrete(&self) -> usize {
+		self.layout().1
+	}
+	#[cfg(feature = "nightly")]
+	#[inline(always)]
+	unsafe fn layout(&self) -> (usize, usize) {
+		let metadata = transmute::<&'static (), std::ptr::DynMetadata<dyn Any>>(self.to());
+		(metadata.size_of(), metadata.align_of())
+	}
+	#[cfg(not(feature = "nightly"))]
+	#[inline(always)]
+	unsafe fn layout(&self) -> (usize, usize) {
+		let (size, align) = (vtable_size(self.to()), vtable_align(self.to()));
+		assert!(
+			align.is_power_of_two() && size % align == 0,
+			"unexpected vtable layout"
+		);
+		(size, align)
+	}
+}
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::ptr::Pointee<Metadata = std::ptr::DynMetadata<T>>> Vtable<T> {
+	/// Create the `Vtable<dyn Trait>` of a concrete type `U`.
+	///
+	/// Note that a vtable from a dynamically loaded library won't be positioned
+	/// the same relative to the base in every invocation.
+	#[inline(always)]
+	pub fn of<U: marker::Unsize<T>>() -> Self {
+		Self::of_ptr(std::ptr::null::<U>())
+	}
+	/// Create a `Vtable<dyn Trait>` from the vtable of a `&dyn Trait`.
+	///
+	/// Note that a vtable from a dynamically loaded library won't be positioned
+	/// the same relative to the base in every invocation.
+	#[inline(always)]
+	pub fn of_val(value: &T) -> Self {
+		Self::of_ptr(value)
+	}
+	#[inline(always)]
+	fn of_ptr(ptr: *const T) -> Self {
+		let vtable = std::ptr::metadata(ptr);
+		unsafe { Self::from(transmute::<std::ptr::DynMetadata<T>, &'static ()>(vtable)) }
 	}
 }
 impl<T: ?Sized> Clone for Vtable<T> {
 	#[inline(always)]
 	fn clone(&self) -> Self {
-		Self(self.0, marker::PhantomData)
+		*self
 	}
 }
 impl<T: ?Sized> Copy for Vtable<T> {}
@@ -176,13 +497,13 @@ impl<T: ?Sized> Eq for Vtable<T> {}
 impl<T: ?Sized> hash::Hash for Vtable<T> {
 	#[inline(always)]
 	fn hash<H: hash::Hasher>(&self, state: &mut H) {
-		self.0.hash(state)
+		self.0.hash(state);
 	}
 }
 impl<T: ?Sized> PartialOrd for Vtable<T> {
 	#[inline(always)]
 	fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-		self.0.partial_cmp(&other.0)
+		Some(self.cmp(other))
 	}
 }
 impl<T: ?Sized> Ord for Vtable<T> {
@@ -198,16 +519,76 @@ impl<T: ?Sized> fmt::Debug for Vtable<T> {
 			.finish()
 	}
 }
+/// Formats as `"<build-id>:<type-id>:<offset>"`, with the type id and offset
+/// in hex. This is the form it's serialized as by human-readable serializers,
+/// unless it's serialized with the name of its type or a registered name.
+///
+/// ```
+/// use relative::{vtable, Vtable};
+/// use std::fmt::Display;
+///
+/// let a: Vtable<dyn Display> = vtable!(String => dyn Display);
+/// let b: Vtable<dyn Display> = a.to_string().parse().unwrap();
+/// assert_eq!(a, b);
+/// ```
+impl<T: ?Sized + 'static> fmt::Display for Vtable<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		wire::Display::<T>(Offset(self.0), marker::PhantomData).fmt(f)
+	}
+}
+/// Parses the form written by its `Display` impl, checking that it came from
+/// this binary and that it was formatted as a `Vtable<T>`.
+impl<T: ?Sized + 'static> str::FromStr for Vtable<T> {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let Offset(offset) = wire::from_str::<T>(s)?;
+		check_vtable::<T>(offset)?;
+		Ok(Self::new(offset))
+	}
+}
+/// Safely create a [`Vtable<dyn Trait>`](Vtable), either of a concrete type or,
+/// prefixed with `val`, from a `&dyn Trait`.
+///
+/// ```
+/// use relative::{vtable, Vtable};
+/// use std::fmt::Display;
+///
+/// let a: Vtable<dyn Display> = vtable!(String => dyn Display);
+/// let b: Vtable<dyn Display> = vtable!(&'static str => dyn Display);
+///
+/// let x: Box<dyn Display> = Box::new(String::from("hello world"));
+/// let c: Vtable<dyn Display> = vtable!(val &*x => dyn Display);
+/// # let _ = (a, b, c);
+/// ```
+///
+/// Note that a vtable from a dynamically loaded library won't be positioned
+/// the same relative to the base in every invocation.
+#[macro_export]
+macro_rules! vtable {
+	(val $value:expr => dyn $($bounds:tt)+) => {{
+		#[allow(unused_parens)]
+		let value: &(dyn $($bounds)+) = $value;
+		#[allow(unused_unsafe)]
+		let vtable = unsafe { $crate::Vtable::<dyn $($bounds)+>::__from_ptr(value) };
+		vtable
+	}};
+	($concrete:ty => dyn $($bounds:tt)+) => {{
+		#[allow(unused_parens)]
+		let ptr: *const (dyn $($bounds)+) = ::std::ptr::null::<$concrete>();
+		#[allow(unused_unsafe)]
+		let vtable = unsafe { $crate::Vtable::<dyn $($bounds)+>::__from_ptr(ptr) };
+		vtable
+	}};
+}
+
 impl<T: ?Sized + 'static> Serialize for Vtable<T> {
 	#[inline]
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
 		S: Serializer,
 	{
-		<(Uuid, u64, usize) as Serialize>::serialize(
-			&(build_id::get(), type_id::<T>(), self.0),
-			serializer,
-		)
+		serialize_offset::<T, _>(Offset(self.0), serializer)
 	}
 }
 impl<'de, T: ?Sized + 'static> Deserialize<'de> for Vtable<T> {
@@ -216,39 +597,88 @@ impl<'de, T: ?Sized + 'static> Deserialize<'de> for Vtable<T> {
 	where
 		D: Deserializer<'de>,
 	{
-		<(Uuid, u64, usize) as Deserialize<'de>>::deserialize(deserializer).and_then(
-			|(build, id, ptr)| {
-				let local = build_id::get();
-				if build == local {
-					if id == type_id::<T>() {
-						Ok(Self::new(ptr))
-					} else {
-						Err(de::Error::custom(format_args!(
-							"relative reference to wrong type ???:{}, expected {}:{}",
-							id,
-							type_name::<T>(),
-							type_id::<T>()
-						)))
-					}
-				} else {
-					Err(de::Error::custom(format_args!(
-						"relative reference came from a different binary {}, expected {}",
-						build, local
-					)))
-				}
-			},
-		)
+		let Offset(offset) = deserialize_offset::<T, _>(deserializer)?;
+		check_vtable::<T>(offset).map_err(Error::into_de)?;
+		Ok(Self::new(offset))
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::{type_id, Vtable};
-	use bincode;
-	use metatype;
+	use super::{
+		legacy_type_id, type_id, wire::Encoded, BinaryIdentity, Error, Func, Remap, Static, StaticDyn, Vtable, VtableTable
+	};
+	use crate::{register_boxed, register_vtable, static_dyn};
 	use serde_derive::{Deserialize, Serialize};
-	use serde_json;
-	use std::{any::Any, env, fmt, process, str};
+	use std::{
+		alloc::{self, Layout}, any::{Any, TypeId}, env, fmt, process, ptr, rc::Rc, str, sync::Arc
+	};
+
+	/// Run the test `name` in a child process, as it changes process-wide
+	/// state. Returns whether this is that child, which should then run the
+	/// test and print `success_<name>_relative`.
+	fn isolated(name: &str) -> bool {
+		if env::var(spawned_var(name)).is_ok() {
+			return true;
+		}
+		spawn(name, "");
+		false
+	}
+
+	/// Send `a` to the test `name` run in other processes, which check what
+	/// they deserialize with `check`, to verify it resolves the same when the
+	/// binary is loaded at a different address.
+	fn cross_process<T>(name: &str, a: &T, check: impl Fn(T))
+	where
+		T: serde::Serialize + serde::de::DeserializeOwned,
+	{
+		if cfg!(miri) {
+			return;
+		}
+		if let Ok(x) = env::var(spawned_var(name)) {
+			let (a2, bc): (T, Vec<u8>) = serde_json::from_str(&x).unwrap();
+			check(a2);
+			check(bincode::deserialize(&bc).unwrap());
+			println!("success_{name}_relative");
+			return;
+		}
+		let payload = serde_json::to_string(&(a, bincode::serialize(a).unwrap())).unwrap();
+		for _ in 0..10 {
+			spawn(name, &payload);
+		}
+	}
+
+	fn spawned_var(name: &str) -> String {
+		format!("SPAWNED_{}_RELATIVE", name.to_uppercase())
+	}
+
+	/// Run the test `name` in another process, with `value` in its
+	/// environment, and check it succeeds.
+	fn spawn(name: &str, value: &str) {
+		let output = process::Command::new(env::current_exe().unwrap())
+			.arg("--nocapture")
+			.arg("--exact")
+			.arg(format!("tests::{name}"))
+			.env(spawned_var(name), value)
+			.output()
+			.unwrap();
+		assert!(
+			str::from_utf8(&output.stdout)
+				.unwrap()
+				.contains(&format!("success_{name}_relative"))
+				&& output.status.success(),
+			"{:?}",
+			output
+		);
+	}
+
+	/// Alter a serialized vtable, to check how a tampered or foreign one is
+	/// handled.
+	fn tamper(a: &[u8], f: impl FnOnce(&mut Encoded)) -> Vec<u8> {
+		let mut encoded = bincode::deserialize(a).unwrap();
+		f(&mut encoded);
+		bincode::serialize(&encoded).unwrap()
+	}
 
 	#[test]
 	fn type_id_sanity() {
@@ -260,6 +690,920 @@ mod tests {
 		assert_eq!(type_id::<A>(), type_id::<A>());
 	}
 
+	#[test]
+	fn type_id_repr() {
+		struct A;
+		for (id, bits) in &[
+			(TypeId::of::<u8>(), type_id::<u8>()),
+			(TypeId::of::<A>(), type_id::<A>()),
+			(TypeId::of::<dyn Any>(), type_id::<dyn Any>()),
+		] {
+			assert_eq!(format!("{id:?}"), format!("TypeId({bits:#034x})"));
+		}
+	}
+
+	#[test]
+	fn type_id_wire() {
+		fn one() -> u8 {
+			1
+		}
+		let a = unsafe { Func::<fn() -> u8>::from(one) };
+		let b: Func<fn() -> u8> = serde_json::from_value(serde_json::to_value(a).unwrap()).unwrap();
+		assert_eq!(a, b);
+		let a = vtable!(u8 => dyn fmt::Display);
+		let b: Vtable<dyn fmt::Display> =
+			serde_json::from_value(serde_json::to_value(a).unwrap()).unwrap();
+		assert_eq!(a, b);
+	}
+
+	#[test]
+	fn legacy_format() {
+		let a = vtable!(u8 => dyn fmt::Display);
+		#[allow(clippy::cast_sign_loss)]
+		let legacy = (
+			build_id::get(),
+			legacy_type_id::<dyn fmt::Display>(),
+			a.0 as usize,
+		);
+		let b: Vtable<dyn fmt::Display> =
+			bincode::deserialize(&bincode::serialize(&legacy).unwrap()).unwrap();
+		assert_eq!(a, b);
+		let bincoded = bincode::serialize(&legacy).unwrap();
+		assert_eq!(tamper(&bincoded, |_| ()), bincoded);
+		let b: Vtable<dyn fmt::Display> =
+			serde_json::from_str(&serde_json::to_string(&legacy).unwrap()).unwrap();
+		assert_eq!(a, b);
+		let err =
+			bincode::deserialize::<Vtable<dyn fmt::Debug>>(&bincode::serialize(&legacy).unwrap())
+				.unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::TypeMismatch { got, .. }) if got == legacy_type_id::<dyn fmt::Display>().into()
+		));
+		if cfg!(target_pointer_width = "32") {
+			let legacy = (legacy.0, legacy.1, u64::from(u32::MAX) + 1);
+			let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(
+				&bincode::serialize(&legacy).unwrap(),
+			)
+			.unwrap_err();
+			assert!(matches!(
+				Error::from_serde(&err),
+				Some(Error::OffsetOutOfRange { .. })
+			));
+		}
+	}
+
+	#[test]
+	fn unsupported_format() {
+		let a = bincode::serialize(&vtable!(u8 => dyn fmt::Display)).unwrap();
+		assert_eq!(tamper(&a, |_| ()), a);
+		let mut errors = Vec::new();
+		for version in [0, super::FORMAT_VERSION + 1] {
+			let a = tamper(&a, |a| a.version = Some(version));
+			let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(&a).unwrap_err();
+			errors.push((Error::from_serde(&err), err.to_string()));
+		}
+		// the flags aren't a field of `Encoded`, so set an unknown one in the
+		// tag as serialized by serde_json, where it's an array
+		let mut a = serde_json::to_value(bincode::deserialize::<Encoded>(&a).unwrap()).unwrap();
+		a[0][1] = 0x80.into();
+		let err = serde_json::from_value::<Vtable<dyn fmt::Display>>(a).unwrap_err();
+		errors.push((Error::from_serde(&err), err.to_string()));
+		for (err, message) in errors {
+			assert!(matches!(err, Some(Error::UnsupportedFormat { .. })));
+			assert!(message.contains(&format!(
+				"expected version {} or the untagged 0.2 layout",
+				super::FORMAT_VERSION
+			)));
+		}
+	}
+
+	#[test]
+	fn offset_out_of_range() {
+		let a = bincode::serialize(&vtable!(u8 => dyn fmt::Display)).unwrap();
+		let a = tamper(&a, |a| a.offset = i64::MAX);
+		let res = bincode::deserialize::<Vtable<dyn fmt::Display>>(&a);
+		if cfg!(all(target_pointer_width = "64", target_os = "linux")) {
+			assert_eq!(
+				Error::from_serde(&res.unwrap_err()),
+				Some(Error::OutsideImage {
+					offset: Some(i64::MAX)
+				})
+			);
+		} else if cfg!(target_pointer_width = "64") {
+			assert!(res.is_ok());
+		} else {
+			assert!(matches!(
+				Error::from_serde(&res.unwrap_err()),
+				Some(Error::OffsetOutOfRange { offset: i64::MAX })
+			));
+		}
+	}
+
+	#[test]
+	fn remap() {
+		fn foreign(a: &[u8], offset: i64) -> Vec<u8> {
+			tamper(a, |a| {
+				a.build = uuid::Uuid::from_bytes([0xee; 16]);
+				a.offset = offset;
+			})
+		}
+		let build = uuid::Uuid::from_bytes([0xee; 16]);
+		let a = vtable!(u8 => dyn fmt::Display);
+		let ser = bincode::serialize(&a).unwrap();
+		let err =
+			bincode::deserialize::<Vtable<dyn fmt::Display>>(&foreign(&ser, 0x1234)).unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::BuildMismatch { .. })
+		));
+
+		let mut remap = Remap::new(build);
+		remap.insert("<u8 as core::fmt::Display>::{vtable}", 0x1234, a.0 as i64);
+		super::register_remap(remap);
+		assert_eq!(a, bincode::deserialize(&foreign(&ser, 0x1234)).unwrap());
+		let legacy = (build, legacy_type_id::<dyn fmt::Display>(), 0x1234_u64);
+		assert_eq!(
+			a,
+			bincode::deserialize(&bincode::serialize(&legacy).unwrap()).unwrap()
+		);
+		let err =
+			bincode::deserialize::<Vtable<dyn fmt::Display>>(&foreign(&ser, 0x5678)).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::Unmapped {
+				build,
+				offset: 0x5678
+			})
+		);
+		let err =
+			bincode::deserialize::<Vtable<dyn fmt::Debug>>(&foreign(&ser, 0x1234)).unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::TypeMismatch { .. })
+		));
+		assert_eq!(
+			a,
+			format!("{}:{:x}:1234", build, type_id::<dyn fmt::Display>())
+				.parse()
+				.unwrap()
+		);
+	}
+
+	#[cfg(feature = "symbols")]
+	#[test]
+	fn symbols() {
+		use super::symbols::{is_vtable_for, Encoded, Image};
+		let image = Image::open(env::current_exe().unwrap()).unwrap();
+		let a = vtable!(u8 => dyn fmt::Display);
+		let encoded: Encoded = bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap();
+		assert_eq!(encoded.build, build_id::get());
+		assert_eq!(encoded.type_id, type_id::<dyn fmt::Display>());
+		assert_eq!(encoded.offset, a.0 as i64);
+		let json: Encoded = serde_json::from_str(&serde_json::to_string(&a).unwrap()).unwrap();
+		assert_eq!(json.offset, encoded.offset);
+		assert!(is_vtable_for(
+			"<u8 as core::fmt::Display>::{vtable}",
+			"dyn core::fmt::Display + core::marker::Send"
+		));
+		assert!(!is_vtable_for(
+			"<u8 as core::fmt::Debug>::{vtable}",
+			"dyn core::fmt::Display"
+		));
+		assert!(!format!("{a:#?}").contains("{vtable}"));
+		// release builds have no debug info to name vtables by
+		if image.vtables().next().is_some() {
+			assert_eq!(
+				image.vtable(encoded.offset),
+				Some("<u8 as core::fmt::Display>::{vtable}")
+			);
+			assert_eq!(a.symbol(), Some("<u8 as core::fmt::Display>::{vtable}"));
+			assert!(format!("{:?}", a.with_symbol())
+				.contains("symbol: Some(\"<u8 as core::fmt::Display>::{vtable}\")"));
+			assert!(image.vtables().any(|(name, offset)| name
+				== "<u8 as core::fmt::Debug>::{vtable}"
+				&& offset == vtable!(u8 => dyn fmt::Debug).0 as i64));
+		}
+	}
+
+	#[test]
+	fn concrete() {
+		let a = vtable!(String => dyn fmt::Debug);
+		assert_eq!(unsafe { a.size_of_concrete() }, size_of::<String>());
+		assert_eq!(unsafe { a.align_of_concrete() }, align_of::<String>());
+		let b = vtable!(u16 => dyn fmt::Display);
+		assert_eq!(
+			unsafe { (b.size_of_concrete(), b.align_of_concrete()) },
+			(2, 2)
+		);
+
+		let rc = Rc::new(0_u8);
+		let a = vtable!(Rc<u8> => dyn fmt::Debug);
+		unsafe {
+			let layout =
+				Layout::from_size_align(a.size_of_concrete(), a.align_of_concrete()).unwrap();
+			let data = alloc::alloc(layout).cast::<()>();
+			data.cast::<Rc<u8>>().write(rc.clone());
+			assert_eq!(Rc::strong_count(&rc), 2);
+			a.drop_in_place(data);
+			assert_eq!(Rc::strong_count(&rc), 1);
+			alloc::dealloc(data.cast(), layout);
+		}
+	}
+
+	#[test]
+	fn negative_offset() {
+		use bincode::Options;
+		let a = Vtable::<dyn fmt::Display>::new(-16);
+		let json = serde_json::to_string(&a).unwrap();
+		if cfg!(feature = "type-names") {
+			assert!(json.ends_with(",-16]"), "{}", json);
+		} else {
+			assert!(json.ends_with(":-10\""), "{}", json);
+		}
+		assert_eq!(a, serde_json::from_str(&json).unwrap());
+		let fixed = bincode::serialize(&a).unwrap();
+		assert_eq!(fixed[fixed.len() - 8..], (-16_i64).to_le_bytes());
+		let varint = bincode::options().serialize(&a).unwrap();
+		assert_eq!(varint.last(), Some(&31));
+		assert_eq!(a, bincode::options().deserialize(&varint).unwrap());
+	}
+
+	#[test]
+	fn self_check() {
+		super::register_vtable_as("i128", vtable!(i128 => dyn fmt::Binary));
+		let check = super::self_check();
+		assert!(check.samples().len() > 4);
+		assert!(check.samples().iter().any(|(name, _)| name == "i128"));
+		#[cfg(target_os = "linux")]
+		{
+			assert!(check.is_ok(), "{}", check);
+			assert!(matches!(
+				check.linkage(),
+				super::Linkage::Pie | super::Linkage::NonPie
+			));
+			assert!(check.to_string().starts_with("ok: "));
+		}
+		#[cfg(not(target_os = "linux"))]
+		assert_eq!(check.verdict(), super::Verdict::Unverified);
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn outside_image() {
+		static ARRAY: [u64; 4] = [1, 2, 3, 4];
+		fn double(x: usize) -> usize {
+			x * 2
+		}
+		fn outside<T: serde::de::DeserializeOwned>(a: &[u8]) -> bool {
+			let err = bincode::deserialize::<T>(a).err().unwrap();
+			matches!(Error::from_serde(&err), Some(Error::OutsideImage { .. }))
+		}
+		// alter the payload, which is the last element of the tuple as
+		// serialized by serde_json
+		fn patch<T: serde::Serialize + serde::de::DeserializeOwned>(
+			a: T, f: impl FnOnce(&mut serde_json::Value),
+		) -> Error {
+			let mut a = serde_json::to_value(a).unwrap();
+			f(a.as_array_mut().unwrap().last_mut().unwrap());
+			Error::from_serde(&serde_json::from_value::<T>(a).err().unwrap()).unwrap()
+		}
+		let a = vtable!(u8 => dyn fmt::Display);
+		for b in [
+			Vtable::<dyn fmt::Display>::new(a.0 + 1),
+			Vtable::new(1 << 40),
+			Vtable::new(-(1 << 40)),
+		] {
+			assert!(outside::<Vtable<dyn fmt::Display>>(
+				&bincode::serialize(&b).unwrap()
+			));
+			assert!(matches!(
+				b.to_string().parse::<Vtable<dyn fmt::Display>>(),
+				Err(Error::OutsideImage { .. })
+			));
+		}
+
+		let a = unsafe { Func::<fn(usize) -> usize>::from(double) };
+		assert!(
+			bincode::deserialize::<Func<fn(usize) -> usize>>(&bincode::serialize(&a).unwrap())
+				.is_ok()
+		);
+		assert!(matches!(
+			patch(a, |a| *a = (1_i64 << 40).into()),
+			Error::OutsideImage { .. }
+		));
+
+		let a = unsafe { Static::<[u64]>::from(&ARRAY) };
+		assert!(bincode::deserialize::<Static<[u64]>>(&bincode::serialize(&a).unwrap()).is_ok());
+		for index in 0..2 {
+			assert!(matches!(
+				patch(a, |a| a[index] = (1_i64 << 40).into()),
+				Error::OutsideImage { .. }
+			));
+		}
+
+		let a = static_dyn!(&1_u64 => dyn fmt::Debug + Sync);
+		assert!(bincode::deserialize::<StaticDyn<dyn fmt::Debug + Sync>>(
+			&bincode::serialize(&a).unwrap()
+		)
+		.is_ok());
+		assert_eq!(
+			patch(a, |a| a[0] = serde_json::Value::Null),
+			Error::OutsideImage { offset: None }
+		);
+	}
+
+	#[test]
+	fn strict() {
+		static REGISTERED: u64 = 1;
+		static UNREGISTERED: u64 = 2;
+		// distinct bodies, such that they aren't merged in release builds
+		#[inline(never)]
+		fn registered_fn(x: u32) -> u32 {
+			x.wrapping_add(1)
+		}
+		#[inline(never)]
+		fn unregistered_fn(x: u32) -> u32 {
+			x.wrapping_mul(3)
+		}
+		if !isolated("strict") {
+			return;
+		}
+		let a = vtable!(u8 => dyn fmt::Display);
+		let b = vtable!(String => dyn fmt::Display);
+		let c = vtable!(u8 => dyn fmt::Debug);
+		register_vtable!(u8, u16 => dyn fmt::Display);
+		register_vtable!(u8 => dyn fmt::Debug + Sync);
+		let ser = |a| bincode::serialize(&a).unwrap();
+		assert!(!super::is_strict());
+		assert!(bincode::deserialize::<Vtable<dyn fmt::Display>>(&ser(b)).is_ok());
+		super::set_strict(true);
+		assert!(bincode::deserialize::<Vtable<dyn fmt::Display>>(&ser(a)).is_ok());
+		let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(&ser(b)).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::Unregistered {
+				offset: b.0 as i64,
+				expected_name: "dyn core::fmt::Display"
+			})
+		);
+		assert!(matches!(
+			b.to_string().parse::<Vtable<dyn fmt::Display>>(),
+			Err(Error::Unregistered { .. })
+		));
+		let err = bincode::deserialize::<Vtable<dyn fmt::Debug>>(&bincode::serialize(&c).unwrap())
+			.unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::Unregistered { .. })
+		));
+		let registered = static_dyn!(&1_u8 => dyn fmt::Debug + Sync);
+		let unregistered = static_dyn!(&1_u16 => dyn fmt::Debug + Sync);
+		super::register_static_dyn(registered);
+		assert!(bincode::deserialize::<StaticDyn<dyn fmt::Debug + Sync>>(
+			&bincode::serialize(&registered).unwrap()
+		)
+		.is_ok());
+		assert!(bincode::deserialize::<StaticDyn<dyn fmt::Debug + Sync>>(
+			&bincode::serialize(&unregistered).unwrap()
+		)
+		.is_err());
+		let statics = unsafe { (Static::from(&REGISTERED), Static::from(&UNREGISTERED)) };
+		let funcs = unsafe {
+			(
+				Func::<fn(u32) -> u32>::from(registered_fn),
+				Func::<fn(u32) -> u32>::from(unregistered_fn),
+			)
+		};
+		super::register_static(statics.0);
+		super::register_func(funcs.0);
+		assert!(
+			bincode::deserialize::<Static<u64>>(&bincode::serialize(&statics.0).unwrap()).is_ok()
+		);
+		let err = bincode::deserialize::<Static<u64>>(&bincode::serialize(&statics.1).unwrap())
+			.unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::Unregistered { .. })
+		));
+		assert_ne!(funcs.0, funcs.1);
+		assert!(bincode::deserialize::<Func<fn(u32) -> u32>>(
+			&bincode::serialize(&funcs.0).unwrap()
+		)
+		.is_ok());
+		let err =
+			bincode::deserialize::<Func<fn(u32) -> u32>>(&bincode::serialize(&funcs.1).unwrap())
+				.unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::Unregistered { .. })
+		));
+		super::set_strict(false);
+		assert!(bincode::deserialize::<Vtable<dyn fmt::Display>>(&ser(b)).is_ok());
+		println!("success_strict_relative");
+	}
+
+	#[test]
+	fn named() {
+		// Pretend to have been serialized by another build, which laid out its
+		// vtables differently.
+		fn foreign(a: &[u8]) -> Vec<u8> {
+			tamper(a, |a| {
+				a.build = uuid::Uuid::from_bytes([0xff; 16]);
+				a.offset = 0x1234;
+			})
+		}
+		if !isolated("named") {
+			return;
+		}
+		let a = vtable!(u8 => dyn fmt::Display);
+		let b = vtable!(String => dyn fmt::Display);
+		let c = vtable!(u8 => dyn fmt::Debug);
+		register_vtable!(u8 => dyn fmt::Display);
+		register_vtable!(u8 => dyn fmt::Debug);
+		super::register_vtable_as("string", b);
+		let plain = bincode::serialize(&a).unwrap();
+		assert!(!super::is_named());
+		super::set_named(true);
+
+		let named = bincode::serialize(&a).unwrap();
+		assert!(named.len() > plain.len());
+		assert_eq!(a, bincode::deserialize(&named).unwrap());
+		assert_eq!(a, bincode::deserialize(&foreign(&named)).unwrap());
+		let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(&foreign(&plain)).unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::BuildMismatch { .. })
+		));
+
+		let named = foreign(&bincode::serialize(&b).unwrap());
+		assert_eq!(b, bincode::deserialize(&named).unwrap());
+		let named = tamper(&named, |a| a.name = Some("strinS".to_owned()));
+		let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(&named).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::UnknownName {
+				name: "strinS".to_owned(),
+				expected_name: "dyn core::fmt::Display"
+			})
+		);
+
+		let named = foreign(&bincode::serialize(&c).unwrap());
+		let err = bincode::deserialize::<Vtable<dyn fmt::Display>>(&named).unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::TypeMismatch { .. })
+		));
+
+		let json = serde_json::to_string(&a).unwrap();
+		assert!(json.starts_with('[') && json.contains("\"u8\""));
+		assert_eq!(a, serde_json::from_str(&json).unwrap());
+		let unregistered = vtable!(u16 => dyn fmt::Display);
+		assert_eq!(
+			bincode::serialize(&unregistered).unwrap().len(),
+			plain.len()
+		);
+		super::set_named(false);
+		println!("success_named_relative");
+	}
+
+	#[cfg(feature = "mac")]
+	#[test]
+	fn mac() {
+		fn unauthenticated<T: serde::de::DeserializeOwned>(a: &[u8]) -> bool {
+			let err = bincode::deserialize::<T>(a).err().unwrap();
+			Error::from_serde(&err) == Some(Error::Unauthenticated)
+		}
+		if !isolated("mac") {
+			return;
+		}
+		let a = vtable!(u8 => dyn fmt::Display);
+		let b = static_dyn!(&1_u8 => dyn fmt::Debug + Sync);
+		let plain = bincode::serialize(&a).unwrap();
+		let plain_string = a.to_string();
+		super::set_mac_key(b"secret");
+
+		let tagged = bincode::serialize(&a).unwrap();
+		assert_eq!(tagged.len(), plain.len() + 8 + 32);
+		assert_eq!(a, bincode::deserialize(&tagged).unwrap());
+		assert!(unauthenticated::<Vtable<dyn fmt::Display>>(&plain));
+		let forged = tamper(&tagged, |a| a.offset ^= 0x10);
+		assert!(unauthenticated::<Vtable<dyn fmt::Display>>(&forged));
+		let forged = tamper(&tagged, |a| {
+			a.type_name = match a.type_name {
+				Some(_) => None,
+				None => Some("dyn core::fmt::Display".to_owned()),
+			};
+		});
+		assert!(unauthenticated::<Vtable<dyn fmt::Display>>(&forged));
+
+		let string = a.to_string();
+		assert!(string.starts_with(&plain_string) && string.len() == plain_string.len() + 65);
+		assert_eq!(a, string.parse().unwrap());
+		assert_eq!(
+			plain_string.parse::<Vtable<dyn fmt::Display>>(),
+			Err(Error::Unauthenticated)
+		);
+		assert_eq!(
+			a,
+			serde_json::from_str(&serde_json::to_string(&a).unwrap()).unwrap()
+		);
+
+		let tagged = bincode::serialize(&b).unwrap();
+		assert_eq!(b, bincode::deserialize(&tagged).unwrap());
+		// `Encoded` only holds vtables, so alter the vtable offset in the
+		// payload preceding the MAC as serialized by serde_json
+		let mut forged = serde_json::to_value(b).unwrap();
+		let payload = forged
+			.as_array_mut()
+			.unwrap()
+			.iter_mut()
+			.nth_back(1)
+			.unwrap();
+		payload[1] = (b.vtable().0 ^ 0x10).into();
+		let err = serde_json::from_value::<StaticDyn<dyn fmt::Debug + Sync>>(forged).unwrap_err();
+		assert_eq!(Error::from_serde(&err), Some(Error::Unauthenticated));
+
+		let session = BinaryIdentity::local().check().unwrap();
+		let tagged = session.scope(|| bincode::serialize(&a).unwrap());
+		assert_eq!(tagged.len(), 8 + 8 + 32);
+		assert_eq!(a, session.scope(|| bincode::deserialize(&tagged).unwrap()));
+
+		let mut table = VtableTable::new();
+		let values = table.encode(|| bincode::serialize(&[a, a]).unwrap());
+		let table = bincode::serialize(&table).unwrap();
+		let table: VtableTable = bincode::deserialize(&table).unwrap();
+		assert_eq!(
+			[a, a],
+			table.decode(|| bincode::deserialize::<[Vtable<_>; 2]>(&values).unwrap())
+		);
+
+		let tagged = bincode::serialize(&a).unwrap();
+		super::set_mac_key(b"other");
+		assert!(unauthenticated::<Vtable<dyn fmt::Display>>(&tagged));
+		super::clear_mac_key();
+		assert_eq!(a, bincode::deserialize(&plain).unwrap());
+		println!("success_mac_relative");
+	}
+
+	#[test]
+	fn human_readable() {
+		let a = vtable!(u8 => dyn fmt::Display);
+		let string = a.to_string();
+		assert_eq!(
+			string,
+			format!(
+				"{}:{:x}:{}{:x}",
+				build_id::get(),
+				type_id::<dyn fmt::Display>(),
+				if a.0 < 0 { "-" } else { "" },
+				a.0.unsigned_abs()
+			)
+		);
+		if cfg!(feature = "type-names") {
+			assert!(serde_json::to_string(&a)
+				.unwrap()
+				.contains(&format!("{:?}", std::any::type_name::<dyn fmt::Display>())));
+		} else {
+			assert_eq!(serde_json::to_string(&a).unwrap(), format!("{string:?}"));
+		}
+		assert_eq!(a, string.parse().unwrap());
+		assert_eq!(
+			a,
+			serde_json::from_str::<Vtable<_>>(&format!("{string:?}")).unwrap()
+		);
+		assert!(matches!(
+			string.parse::<Vtable<dyn fmt::Debug>>(),
+			Err(Error::TypeMismatch { .. })
+		));
+		for malformed in ["", "a:b:c", &string[1..], &format!("{string}:0")] {
+			assert!(matches!(
+				malformed.parse::<Vtable<dyn fmt::Display>>(),
+				Err(Error::Malformed { .. })
+			));
+		}
+		let err = serde_json::from_str::<Vtable<dyn fmt::Display>>("\"a:b:c\"").unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::Malformed { .. })
+		));
+	}
+
+	#[test]
+	fn session() {
+		let local = BinaryIdentity::local();
+		let remote: BinaryIdentity =
+			bincode::deserialize(&bincode::serialize(&local).unwrap()).unwrap();
+		assert_eq!(local, remote);
+		let session = remote.check().unwrap();
+		let a = (
+			vtable!(u8 => dyn fmt::Display),
+			static_dyn!(&1_u8 => dyn fmt::Debug + Sync),
+		);
+		let bytes = session.scope(|| bincode::serialize(&a).unwrap());
+		assert_eq!(bytes.len(), 8 + 1 + 8 + 8);
+		let b = session.scope(|| bincode::deserialize(&bytes).unwrap());
+		assert_eq!(a, b);
+		let json = session.scope(|| serde_json::to_string(&a).unwrap());
+		assert_eq!(a, session.scope(|| serde_json::from_str(&json).unwrap()));
+		assert!(
+			bincode::deserialize::<(Vtable<dyn fmt::Display>, StaticDyn<dyn fmt::Debug + Sync>)>(
+				&bytes
+			)
+			.is_err()
+		);
+		let _ = std::panic::catch_unwind(|| session.scope(|| panic!()));
+		assert_eq!(
+			a,
+			bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap()
+		);
+
+		let other: BinaryIdentity = bincode::deserialize(
+			&bincode::serialize(&(uuid::Uuid::nil(), local.layout())).unwrap(),
+		)
+		.unwrap();
+		assert!(matches!(other.check(), Err(Error::BuildMismatch { .. })));
+		let other: BinaryIdentity = bincode::deserialize(
+			&bincode::serialize(&(local.build_id(), !local.layout())).unwrap(),
+		)
+		.unwrap();
+		assert!(matches!(other.check(), Err(Error::LayoutMismatch { .. })));
+	}
+
+	#[test]
+	fn vtable_table() {
+		let batch = (0..100)
+			.map(|i| {
+				if i % 3 == 0 {
+					vtable!(u8 => dyn fmt::Display)
+				} else {
+					vtable!(u16 => dyn fmt::Display)
+				}
+			})
+			.collect::<Vec<_>>();
+		let mut table = VtableTable::new();
+		let values = table.encode(|| bincode::serialize(&batch).unwrap());
+		assert_eq!(values.len(), 8 + 4 * 100);
+		assert_eq!(table.len(), 2);
+		let table: VtableTable =
+			serde_json::from_str(&serde_json::to_string(&table).unwrap()).unwrap();
+		let b: Vec<Vtable<dyn fmt::Display>> =
+			table.decode(|| bincode::deserialize(&values).unwrap());
+		assert_eq!(batch, b);
+		assert_eq!(
+			batch,
+			bincode::deserialize::<Vec<_>>(&bincode::serialize(&batch).unwrap()).unwrap()
+		);
+
+		let err = table
+			.decode(|| bincode::deserialize::<Vec<Vtable<dyn fmt::Debug>>>(&values))
+			.unwrap_err();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::TypeMismatch { .. })
+		));
+		let err = table
+			.decode(|| bincode::deserialize::<Vtable<dyn fmt::Display>>(&2_u32.to_le_bytes()))
+			.unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::IndexOutOfRange { index: 2, len: 2 })
+		);
+	}
+
+	#[test]
+	fn vtable_macro() {
+		let trait_object: Box<dyn Any> = Box::new(1234_usize);
+		let meta: metatype::TraitObject =
+			metatype::type_coerce(<dyn Any as metatype::Type>::meta(&*trait_object));
+		let a = unsafe { Vtable::<dyn Any>::from(meta.vtable) };
+		assert_eq!(a, vtable!(val &*trait_object => dyn Any));
+		assert_eq!(a, vtable!(usize => dyn Any));
+		let b: Box<dyn fmt::Display> = Box::new("abc");
+		assert_eq!(
+			vtable!(val &*b => dyn fmt::Display),
+			vtable!(&'static str => dyn fmt::Display)
+		);
+		#[cfg(feature = "nightly")]
+		{
+			assert_eq!(a, Vtable::<dyn Any>::of::<usize>());
+			assert_eq!(a, Vtable::of_val(&*trait_object));
+		}
+	}
+
+	#[test]
+	fn reassemble() {
+		let a = vtable!(String => dyn fmt::Display);
+		let a: Vtable<dyn fmt::Display> =
+			bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap();
+		let x = String::from("abc");
+		unsafe {
+			assert_eq!(a.to_ref(ptr::from_ref(&x).cast()).to_string(), "abc");
+			let x = Box::into_raw(Box::new(String::from("def")));
+			assert_eq!(a.to_box(x.cast()).to_string(), "def");
+			let x = Rc::into_raw(Rc::new(String::from("ghi")));
+			assert_eq!(a.to_rc(x.cast()).to_string(), "ghi");
+			let x = Arc::into_raw(Arc::new(String::from("jkl")));
+			assert_eq!(a.to_arc(x.cast()).to_string(), "jkl");
+		}
+	}
+
+	#[test]
+	fn boxed() {
+		trait Shape: super::boxed::Serialize + super::boxed::Deserialize {
+			fn area(&self) -> u64;
+		}
+		#[derive(Serialize, Deserialize)]
+		struct Square(u64);
+		impl Shape for Square {
+			fn area(&self) -> u64 {
+				self.0 * self.0
+			}
+		}
+		#[derive(Serialize, Deserialize)]
+		struct Rectangle {
+			w: u64,
+			h: u64,
+		}
+		impl Shape for Rectangle {
+			fn area(&self) -> u64 {
+				self.w * self.h
+			}
+		}
+		#[derive(Serialize, Deserialize)]
+		struct Circle(u64);
+		impl Shape for Circle {
+			fn area(&self) -> u64 {
+				3 * self.0 * self.0
+			}
+		}
+		register_boxed!(Square, Rectangle => dyn Shape);
+		let shapes: Vec<super::boxed::Box<dyn Shape>> = vec![
+			super::boxed::Box::new(Box::new(Square(2))),
+			super::boxed::Box::new(Box::new(Rectangle { w: 2, h: 3 })),
+		];
+		let unbincoded: Vec<super::boxed::Box<dyn Shape>> =
+			bincode::deserialize(&bincode::serialize(&shapes).unwrap()).unwrap();
+		let unjsoned: Vec<super::boxed::Box<dyn Shape>> =
+			serde_json::from_str(&serde_json::to_string(&shapes).unwrap()).unwrap();
+		for shapes in &[unbincoded, unjsoned] {
+			assert_eq!(shapes.iter().map(|s| s.area()).collect::<Vec<_>>(), [4, 6]);
+		}
+
+		let circle: super::boxed::Box<dyn Shape> = super::boxed::Box::new(Box::new(Circle(1)));
+		let err = bincode::deserialize::<super::boxed::Box<dyn Shape>>(
+			&bincode::serialize(&circle).unwrap(),
+		)
+		.err()
+		.unwrap();
+		assert!(matches!(
+			Error::from_serde(&err),
+			Some(Error::Unregistered { .. })
+		));
+		// The data is deserialized as the concrete type of the vtable, whatever
+		// it was serialized as.
+		let forged = bincode::serialize(&(vtable!(Rectangle => dyn Shape), Square(2))).unwrap();
+		assert!(bincode::deserialize::<super::boxed::Box<dyn Shape>>(&forged).is_err());
+	}
+
+	#[test]
+	fn error() {
+		let a = vtable!(u8 => dyn fmt::Debug);
+		let a = tamper(&bincode::serialize(&a).unwrap(), |a| {
+			a.build = uuid::Uuid::nil();
+		});
+		let err = bincode::deserialize::<Vtable<dyn fmt::Debug>>(&a).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::BuildMismatch {
+				got: uuid::Uuid::nil(),
+				expected: build_id::get()
+			})
+		);
+		let a = serde_json::to_string(&vtable!(u8 => dyn fmt::Display)).unwrap();
+		let err = serde_json::from_str::<Vtable<dyn fmt::Debug>>(&a).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::TypeMismatch {
+				got: type_id::<dyn fmt::Display>(),
+				got_name: if cfg!(feature = "type-names") {
+					Some("dyn core::fmt::Display".to_owned())
+				} else {
+					None
+				},
+				expected_name: "dyn core::fmt::Debug",
+				expected_id: type_id::<dyn fmt::Debug>()
+			})
+		);
+		super::register_type::<dyn fmt::Display>();
+		let err = serde_json::from_str::<Vtable<dyn fmt::Debug>>(&a).unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err).unwrap().to_string(),
+			format!(
+				"relative reference to wrong type dyn core::fmt::Display:{}, expected dyn core::fmt::Debug:{}",
+				type_id::<dyn fmt::Display>(),
+				type_id::<dyn fmt::Debug>()
+			)
+		);
+		let err = serde_json::from_str::<Vtable<dyn fmt::Debug>>("[]").unwrap_err();
+		assert_eq!(Error::from_serde(&err), None);
+	}
+
+	#[test]
+	fn func() {
+		extern "C" fn add(a: u32, b: u32) -> u32 {
+			a + b
+		}
+		let a = unsafe { Func::<extern "C" fn(u32, u32) -> u32>::from(add) };
+		let b: Func<extern "C" fn(u32, u32) -> u32> =
+			bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap();
+		assert_eq!(a, b);
+		assert_eq!((unsafe { b.to() })(1, 2), 3);
+		assert!(bincode::deserialize::<Func<fn(u32, u32) -> u32>>(
+			&bincode::serialize(&a).unwrap()
+		)
+		.is_err());
+		// higher-ranked pointers aren't supported, but 'static ones are
+		let len = unsafe { Func::<fn(&'static str) -> usize>::from(str::len) };
+		let len: Func<fn(&'static str) -> usize> =
+			bincode::deserialize(&bincode::serialize(&len).unwrap()).unwrap();
+		assert_eq!((unsafe { len.to() })("hello"), 5);
+	}
+
+	#[test]
+	fn statics() {
+		static X: u64 = 1234;
+		static INVALID: [u8; 2] = [0xc3, 0x28];
+		fn round_trip<T: ?Sized + super::Pod>(a: Static<T>) -> Static<T> {
+			let b = bincode::deserialize(&bincode::serialize(&a).unwrap()).unwrap();
+			assert_eq!(a, b);
+			b
+		}
+		assert_eq!(*round_trip(unsafe { Static::from(&X) }).to(), 1234);
+		assert!(ptr::eq(
+			round_trip(unsafe { Static::from(&X) }).to(),
+			ptr::addr_of!(X)
+		));
+		assert_eq!(round_trip(unsafe { Static::from("") }).to(), "");
+		let _: &() = round_trip(unsafe { Static::from(&()) }).to();
+		assert!(bincode::deserialize::<Static<u32>>(
+			&bincode::serialize(&unsafe { Static::from(&X) }).unwrap()
+		)
+		.is_err());
+		// within a session the type isn't serialized, so this reinterprets it
+		let session = BinaryIdentity::local().check().unwrap();
+		let a =
+			session.scope(|| bincode::serialize(&unsafe { Static::from(&INVALID[..]) }).unwrap());
+		let err = session
+			.scope(|| bincode::deserialize::<Static<str>>(&a))
+			.unwrap_err();
+		assert_eq!(
+			Error::from_serde(&err),
+			Some(Error::InvalidValue {
+				offset: unsafe { Static::from(&INVALID[..]) }.0.unwrap() as i64,
+				expected_name: "str"
+			})
+		);
+	}
+
+	#[test]
+	fn static_dyn() {
+		trait Handler: Sync {
+			fn handle(&self) -> usize;
+		}
+		#[repr(align(64))]
+		struct Zst;
+		impl Handler for Zst {
+			fn handle(&self) -> usize {
+				0
+			}
+		}
+		struct Aligned(usize);
+		impl Handler for Aligned {
+			fn handle(&self) -> usize {
+				self.0
+			}
+		}
+		static A: Aligned = Aligned(1);
+		let handlers: [StaticDyn<dyn Handler>; 3] = [
+			static_dyn!(&Zst => dyn Handler),
+			static_dyn!(&Aligned(2) => dyn Handler),
+			static_dyn!(&A => dyn Handler),
+		];
+		let handlers2: [StaticDyn<dyn Handler>; 3] =
+			serde_json::from_str(&serde_json::to_string(&handlers).unwrap()).unwrap();
+		assert_eq!(handlers, handlers2);
+		let handled: Vec<usize> = handlers2
+			.iter()
+			.map(|h| unsafe { h.to() }.handle())
+			.collect();
+		assert_eq!(handled, [0, 2, 1]);
+		assert_eq!(
+			ptr::from_ref(unsafe { handlers2[0].to() }).cast::<()>() as usize % 64,
+			0
+		);
+	}
+
 	#[test]
 	fn multi_process() {
 		#[derive(Serialize, Deserialize)]
@@ -310,7 +1654,7 @@ mod tests {
 				eq(&a, &a3);
 				assert_eq!(a, a2);
 				assert_eq!(a, a3);
-				println!("success_token_relative {:?}", a2);
+				println!("success_token_relative {a2:?}");
 				return;
 			}
 			let exe = env::current_exe().unwrap();
@@ -335,4 +1679,27 @@ mod tests {
 			}
 		}
 	}
+
+	#[test]
+	fn multi_process_func() {
+		fn double(x: usize) -> usize {
+			x * 2
+		}
+		let a = unsafe { Func::<fn(usize) -> usize>::from(double) };
+		cross_process("multi_process_func", &a, |a2: Func<fn(usize) -> usize>| {
+			assert_eq!(a, a2);
+			assert_eq!((unsafe { a2.to() })(21), 42);
+		});
+	}
+
+	#[test]
+	fn multi_process_static() {
+		let d = unsafe { Static::<str>::from("hello world") };
+		let e = unsafe { Static::<[u16]>::from(&[1, 2, 3]) };
+		cross_process("multi_process_static", &(d, e), |(d2, e2)| {
+			assert_eq!((d, e), (d2, e2));
+			assert_eq!(d2.to(), "hello world");
+			assert_eq!(e2.to(), [1, 2, 3]);
+		});
+	}
 }
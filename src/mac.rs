@@ -0,0 +1,249 @@
+//! Authentication of relative references with a keyed MAC.
+//!
+//! With the "mac" feature and a key set with [`set_mac_key`], references are
+//! serialized with an HMAC-SHA256 tag over the build id, the type id, the
+//! flags and names they're serialized with, and the payload, and references
+//! without a valid tag are rejected with [`Error::Unauthenticated`].
+
+use serde::{
+	de::{self, Deserialize, Deserializer, SeqAccess, Visitor}, ser::{Serialize, Serializer}
+};
+use std::{convert::TryFrom, fmt};
+
+use uuid::Uuid;
+
+use super::{wire::Id, Error, Offset};
+
+/// The bytes a relative reference is authenticated over, in addition to the
+/// build id and type id.
+pub(crate) trait Message {
+	fn write(&self, out: &mut Vec<u8>);
+}
+impl Message for Offset {
+	fn write(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&(self.0 as i64).to_le_bytes());
+	}
+}
+impl Message for u8 {
+	fn write(&self, out: &mut Vec<u8>) {
+		out.push(*self);
+	}
+}
+impl Message for usize {
+	fn write(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&(*self as u64).to_le_bytes());
+	}
+}
+impl Message for u128 {
+	fn write(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&self.to_le_bytes());
+	}
+}
+impl Message for Id {
+	fn write(&self, out: &mut Vec<u8>) {
+		self.0.write(out);
+	}
+}
+impl Message for str {
+	fn write(&self, out: &mut Vec<u8>) {
+		self.len().write(out);
+		out.extend_from_slice(self.as_bytes());
+	}
+}
+impl<T: ?Sized + Message> Message for &T {
+	fn write(&self, out: &mut Vec<u8>) {
+		(**self).write(out);
+	}
+}
+impl<T: Message> Message for Option<T> {
+	fn write(&self, out: &mut Vec<u8>) {
+		match self {
+			None => out.push(0),
+			Some(value) => {
+				out.push(1);
+				value.write(out);
+			}
+		}
+	}
+}
+impl<A: Message, B: Message> Message for (A, B) {
+	fn write(&self, out: &mut Vec<u8>) {
+		self.0.write(out);
+		self.1.write(out);
+	}
+}
+impl<T: Message> Message for Vec<T> {
+	fn write(&self, out: &mut Vec<u8>) {
+		self.len().write(out);
+		for value in self {
+			value.write(out);
+		}
+	}
+}
+
+/// An HMAC-SHA256 tag.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Mac(pub(crate) [u8; 32]);
+impl Serialize for Mac {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_bytes(&self.0)
+	}
+}
+impl<'de> Deserialize<'de> for Mac {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		if deserializer.is_human_readable() {
+			deserializer.deserialize_any(MacVisitor)
+		} else {
+			deserializer.deserialize_bytes(MacVisitor)
+		}
+	}
+}
+struct MacVisitor;
+impl<'de> Visitor<'de> for MacVisitor {
+	type Value = Mac;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a 32-byte relative MAC")
+	}
+
+	fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		<[u8; 32]>::try_from(v)
+			.map(Mac)
+			.map_err(|_| E::invalid_length(v.len(), &self))
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut bytes = Vec::with_capacity(32);
+		while let Some(byte) = seq.next_element()? {
+			bytes.push(byte);
+		}
+		self.visit_bytes(&bytes)
+	}
+}
+
+#[cfg(feature = "mac")]
+mod imp {
+	use hmac::{Hmac, Mac as _};
+	use sha2::Sha256;
+	use std::sync::{PoisonError, RwLock};
+
+	static KEY: RwLock<Option<Vec<u8>>> = RwLock::new(None);
+
+	pub(super) fn set_key(key: Option<&[u8]>) {
+		*KEY.write().unwrap_or_else(PoisonError::into_inner) = key.map(<[u8]>::to_vec);
+	}
+
+	pub(super) fn has_key() -> bool {
+		KEY.read().unwrap_or_else(PoisonError::into_inner).is_some()
+	}
+
+	/// The HMAC of `message`, if a key is set.
+	fn hmac(message: impl FnOnce(&mut Vec<u8>)) -> Option<Hmac<Sha256>> {
+		let key = KEY.read().unwrap_or_else(PoisonError::into_inner);
+		let mut hmac = Hmac::<Sha256>::new_from_slice(key.as_ref()?).ok()?;
+		let mut bytes = Vec::new();
+		message(&mut bytes);
+		hmac.update(&bytes);
+		Some(hmac)
+	}
+
+	pub(super) fn sign(message: impl FnOnce(&mut Vec<u8>)) -> Option<[u8; 32]> {
+		hmac(message).map(|hmac| hmac.finalize().into_bytes().into())
+	}
+
+	pub(super) fn verify(message: impl FnOnce(&mut Vec<u8>), mac: Option<&[u8; 32]>) -> bool {
+		hmac(message).is_none_or(|hmac| mac.is_some_and(|mac| hmac.verify_slice(mac).is_ok()))
+	}
+}
+
+#[cfg(not(feature = "mac"))]
+mod imp {
+	pub(super) fn has_key() -> bool {
+		false
+	}
+
+	pub(super) fn sign(_message: impl FnOnce(&mut Vec<u8>)) -> Option<[u8; 32]> {
+		None
+	}
+
+	pub(super) fn verify(_message: impl FnOnce(&mut Vec<u8>), _mac: Option<&[u8; 32]>) -> bool {
+		true
+	}
+}
+
+/// Set the secret key that relative references are authenticated with in this
+/// process.
+///
+/// Once set, references are serialized with a MAC, and those deserialized
+/// without a valid one are rejected with [`Error::Unauthenticated`], such that
+/// a peer without the key can't forge them. Both peers must use the same key.
+///
+/// ```
+/// use relative::{vtable, Error, Vtable};
+/// use std::fmt::Display;
+///
+/// let unauthenticated = bincode::serialize(&vtable!(u8 => dyn Display)).unwrap();
+/// relative::set_mac_key(b"secret");
+/// let a = bincode::serialize(&vtable!(u8 => dyn Display)).unwrap();
+/// assert!(bincode::deserialize::<Vtable<dyn Display>>(&a).is_ok());
+/// let err = bincode::deserialize::<Vtable<dyn Display>>(&unauthenticated).unwrap_err();
+/// assert_eq!(Error::from_serde(&err), Some(Error::Unauthenticated));
+/// ```
+#[cfg(feature = "mac")]
+pub fn set_mac_key(key: &[u8]) {
+	imp::set_key(Some(key));
+}
+
+/// Clear the secret key set with [`set_mac_key`], such that relative
+/// references are no longer authenticated.
+#[cfg(feature = "mac")]
+pub fn clear_mac_key() {
+	imp::set_key(None);
+}
+
+/// Prefix `message` with the build id and type id.
+fn prefixed(
+	build: Uuid, id: u128, message: impl FnOnce(&mut Vec<u8>),
+) -> impl FnOnce(&mut Vec<u8>) {
+	move |out| {
+		out.extend_from_slice(build.as_bytes());
+		id.write(out);
+		message(out);
+	}
+}
+
+/// The MAC of `message` for the type `id` in `build`, if a key is set.
+pub(crate) fn sign(build: Uuid, id: u128, message: impl FnOnce(&mut Vec<u8>)) -> Option<Mac> {
+	imp::sign(prefixed(build, id, message)).map(Mac)
+}
+
+/// Check the MAC of `message` for the type `id` in `build`, if a key is set.
+pub(crate) fn verify(
+	build: Uuid, id: u128, message: impl FnOnce(&mut Vec<u8>), mac: Option<Mac>,
+) -> Result<(), Error> {
+	if imp::verify(
+		prefixed(build, id, message),
+		mac.as_ref().map(|Mac(mac)| mac),
+	) {
+		Ok(())
+	} else {
+		Err(Error::Unauthenticated)
+	}
+}
+
+/// Whether a key is set, such that references are serialized with a MAC.
+pub(crate) fn active() -> bool {
+	imp::has_key()
+}
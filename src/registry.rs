@@ -0,0 +1,264 @@
+use std::{
+	any::type_name, collections::{BTreeMap, BTreeSet}, sync::{
+		atomic::{AtomicBool, Ordering}, Mutex, PoisonError
+	}
+};
+
+use super::{type_id, Error, FnPtr, Func, Pod, Static, StaticDyn, Vtable};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static NAMED: AtomicBool = AtomicBool::new(false);
+static VTABLES: Registered<isize> = Mutex::new(BTreeMap::new());
+static FUNCS: Registered<isize> = Mutex::new(BTreeMap::new());
+static STATICS: Registered<(isize, usize)> = Mutex::new(BTreeMap::new());
+static STATIC_DYNS: Registered<(Option<isize>, isize)> = Mutex::new(BTreeMap::new());
+static NAMES: Mutex<Names> = Mutex::new(Names {
+	by_name: BTreeMap::new(),
+	by_offset: BTreeMap::new(),
+});
+
+/// The references permitted in strict mode, by the id of the type they refer
+/// to.
+type Registered<K> = Mutex<BTreeMap<u128, BTreeSet<K>>>;
+
+struct Names {
+	by_name: BTreeMap<u128, BTreeMap<&'static str, isize>>,
+	by_offset: BTreeMap<(u128, isize), &'static str>,
+}
+
+/// Permit `vtable` to be deserialized in [strict](set_strict) mode.
+///
+/// This is usually done with the [`register_vtable`](crate::register_vtable!)
+/// macro.
+pub fn register_vtable<T: ?Sized + 'static>(vtable: Vtable<T>) {
+	insert(&VTABLES, type_id::<T>(), vtable.0);
+}
+
+/// Permit `func` to be deserialized in [strict](set_strict) mode.
+pub fn register_func<F: FnPtr>(func: Func<F>) {
+	insert(&FUNCS, type_id::<F>(), func.0);
+}
+
+/// Permit `value` to be deserialized in [strict](set_strict) mode.
+///
+/// Like deserialization, this requires `T` to be [`Pod`]. References to
+/// zero-sized values are always permitted.
+pub fn register_static<T: ?Sized + Pod>(value: Static<T>) {
+	if let (Some(offset), Some(size)) = (value.0, T::size(value.1)) {
+		insert(&STATICS, type_id::<T>(), (offset, size));
+	}
+}
+
+/// Permit `value` and its vtable to be deserialized in [strict](set_strict)
+/// mode.
+pub fn register_static_dyn<T: ?Sized + 'static>(value: StaticDyn<T>) {
+	register_vtable(value.1);
+	insert(&STATIC_DYNS, type_id::<T>(), (value.0, (value.1).0));
+}
+
+fn insert<K: Ord>(set: &Registered<K>, id: u128, key: K) {
+	let _ = set
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.entry(id)
+		.or_default()
+		.insert(key);
+}
+
+fn contains<K: Ord>(set: &Registered<K>, id: u128, key: &K) -> bool {
+	set.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.get(&id)
+		.is_some_and(|keys| keys.contains(key))
+}
+
+/// Register `vtable` under `name`, such that in [named](set_named) mode it can
+/// be deserialized by a different build that has registered the same name for
+/// `T`. It's also permitted in [strict](set_strict) mode.
+///
+/// This is usually done with the [`register_vtable`](crate::register_vtable!)
+/// macro, which names each vtable after its concrete type.
+pub fn register_vtable_as<T: ?Sized + 'static>(name: &'static str, vtable: Vtable<T>) {
+	register_vtable(vtable);
+	let mut names = NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+	let _ = names
+		.by_name
+		.entry(type_id::<T>())
+		.or_default()
+		.insert(name, vtable.0);
+	let _ = names.by_offset.insert((type_id::<T>(), vtable.0), name);
+}
+
+/// Enable or disable strict mode for this process.
+///
+/// In strict mode, a `Vtable<T>` is only deserialized if it has been
+/// [registered](register_vtable()) for `T`, and is otherwise rejected with
+/// [`Error::Unregistered`]. This guarantees that a deserialized `Vtable<T>`
+/// is the vtable for `T` of some concrete type, even if the payload came from
+/// an untrusted peer. Likewise a [`Func`], [`Static`] or [`StaticDyn`] is
+/// only deserialized if it has been registered with [`register_func`],
+/// [`register_static`] or [`register_static_dyn`].
+///
+/// ```
+/// use relative::{register_vtable, vtable, Vtable};
+/// use std::fmt::Display;
+///
+/// register_vtable!(u8, String => dyn Display);
+/// relative::set_strict(true);
+///
+/// let a = bincode::serialize(&vtable!(u8 => dyn Display)).unwrap();
+/// assert!(bincode::deserialize::<Vtable<dyn Display>>(&a).is_ok());
+/// let a = bincode::serialize(&vtable!(u16 => dyn Display)).unwrap();
+/// assert!(bincode::deserialize::<Vtable<dyn Display>>(&a).is_err());
+/// ```
+pub fn set_strict(strict: bool) {
+	STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Whether strict mode is enabled for this process.
+pub fn is_strict() -> bool {
+	STRICT.load(Ordering::Relaxed)
+}
+
+/// Enable or disable named mode for this process.
+///
+/// In named mode, a `Vtable<T>` that has been registered with a name is
+/// serialized with that name and the name of `T`. A receiver running a
+/// different build, which would otherwise reject it with
+/// [`Error::BuildMismatch`], instead resolves it to the vtable it has
+/// registered under the same name for `T`, or rejects it with
+/// [`Error::UnknownName`].
+///
+/// This only applies to vtables serialized individually, rather than within a
+/// [`Session`](crate::Session) or [`VtableTable`](crate::VtableTable), and
+/// not to functions, whose offsets are still only meaningful to the same
+/// build.
+///
+/// ```
+/// use relative::{register_vtable, vtable, Vtable};
+/// use std::fmt::Display;
+///
+/// register_vtable!(u8, String => dyn Display);
+/// relative::set_named(true);
+///
+/// let a = serde_json::to_string(&vtable!(u8 => dyn Display)).unwrap();
+/// assert!(a.contains("\"u8\""));
+/// let b: Vtable<dyn Display> = serde_json::from_str(&a).unwrap();
+/// assert_eq!(b, vtable!(u8 => dyn Display));
+/// ```
+pub fn set_named(named: bool) {
+	NAMED.store(named, Ordering::Relaxed);
+}
+
+/// Whether named mode is enabled for this process.
+pub fn is_named() -> bool {
+	NAMED.load(Ordering::Relaxed)
+}
+
+/// The name the vtable for `T` at `offset` is registered under, in named
+/// mode.
+pub(crate) fn name<T: ?Sized + 'static>(offset: isize) -> Option<&'static str> {
+	if !is_named() {
+		return None;
+	}
+	NAMES
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.by_offset
+		.get(&(type_id::<T>(), offset))
+		.copied()
+}
+
+/// The offsets of the registered vtables, with the name each is registered
+/// under, if any.
+pub(crate) fn vtables() -> BTreeMap<isize, Option<&'static str>> {
+	let names = NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+	let mut vtables = BTreeMap::new();
+	for (id, offsets) in &*VTABLES.lock().unwrap_or_else(PoisonError::into_inner) {
+		for &offset in offsets {
+			let name = names.by_offset.get(&(*id, offset)).copied();
+			let entry = vtables.entry(offset).or_insert(None);
+			*entry = entry.or(name);
+		}
+	}
+	vtables
+}
+
+/// The offset of the vtable for `T` registered under `name`.
+pub(crate) fn resolve<T: ?Sized + 'static>(name: &str) -> Result<isize, Error> {
+	NAMES
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.by_name
+		.get(&type_id::<T>())
+		.and_then(|names| names.get(name))
+		.copied()
+		.ok_or_else(|| Error::UnknownName {
+			name: name.to_owned(),
+			expected_name: type_name::<T>(),
+		})
+}
+
+/// Check that the vtable for `T` at `offset` is registered, in strict mode.
+pub(crate) fn check<T: ?Sized + 'static>(offset: isize) -> Result<(), Error> {
+	permit::<T>(offset, contains(&VTABLES, type_id::<T>(), &offset))
+}
+
+/// Check that the function of type `F` at `offset` is registered, in strict
+/// mode.
+pub(crate) fn check_func<F: 'static>(offset: isize) -> Result<(), Error> {
+	permit::<F>(offset, contains(&FUNCS, type_id::<F>(), &offset))
+}
+
+/// Check that the `size` bytes at `offset` are registered as a `T`, in strict
+/// mode.
+pub(crate) fn check_static<T: ?Sized + 'static>(offset: isize, size: usize) -> Result<(), Error> {
+	permit::<T>(offset, contains(&STATICS, type_id::<T>(), &(offset, size)))
+}
+
+/// Check that the value at `offset` is registered with the vtable for `T` at
+/// `vtable`, in strict mode.
+pub(crate) fn check_static_dyn<T: ?Sized + 'static>(
+	offset: Option<isize>, vtable: isize,
+) -> Result<(), Error> {
+	permit::<T>(
+		offset.unwrap_or(vtable),
+		contains(&STATIC_DYNS, type_id::<T>(), &(offset, vtable)),
+	)
+}
+
+fn permit<T: ?Sized + 'static>(offset: isize, registered: bool) -> Result<(), Error> {
+	if !is_strict() || registered {
+		Ok(())
+	} else {
+		Err(Error::Unregistered {
+			offset: offset as i64,
+			expected_name: type_name::<T>(),
+		})
+	}
+}
+
+/// Permit the vtables of some concrete types for `dyn Trait` to be
+/// deserialized in [strict](crate::set_strict) mode, and register them under
+/// the names of the concrete types for [named](crate::set_named) mode.
+///
+/// ```
+/// use relative::register_vtable;
+/// use std::fmt::{Debug, Display};
+///
+/// register_vtable!(u8, String => dyn Display);
+/// register_vtable!(Vec<u8> => dyn Debug + Send);
+/// ```
+#[macro_export]
+macro_rules! register_vtable {
+	($concrete:ty => dyn $($bounds:tt)+) => {{
+		$crate::register_vtable_as(
+			::std::any::type_name::<$concrete>(),
+			$crate::vtable!($concrete => dyn $($bounds)+),
+		);
+	}};
+	($concrete:ty, $($rest:ty),+ => dyn $($bounds:tt)+) => {{
+		$crate::register_vtable!($concrete => dyn $($bounds)+);
+		$crate::register_vtable!($($rest),+ => dyn $($bounds)+);
+	}};
+}
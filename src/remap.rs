@@ -0,0 +1,177 @@
+use serde::{
+	de::{Deserialize, Deserializer}, ser::{Serialize, Serializer}
+};
+use std::{
+	any::type_name, collections::BTreeMap, convert::TryFrom, sync::{Mutex, PoisonError}
+};
+use uuid::Uuid;
+
+use super::Error;
+
+static REMAPS: Mutex<BTreeMap<Uuid, Remap>> = Mutex::new(BTreeMap::new());
+
+/// A table translating the offsets of vtables in an older build to their
+/// offsets in this one, such that [`Vtable`](crate::Vtable)s serialized by
+/// that build can still be deserialized once it's [registered](register_remap).
+///
+/// Each vtable is keyed by its name, like `<u8 as core::fmt::Display>::{vtable}`,
+/// along with its old offset, as type ids aren't stable between builds: a
+/// vtable is only translated when deserialized as a `Vtable` of the trait it's
+/// named for.
+///
+/// It's generated offline from the two binaries, with [`Remap::between`], and
+/// itself serializable such that it can be shipped alongside the new build.
+///
+/// ```
+/// use relative::Remap;
+/// use uuid::Uuid;
+///
+/// # let old_build = Uuid::nil();
+/// // the offsets of the vtables in each build, as found in their debug info
+/// let old = vec![
+///     ("<u8 as core::fmt::Display>::{vtable}", 0x40),
+///     ("<u16 as core::fmt::Display>::{vtable}", 0x80),
+/// ];
+/// let new = vec![("<u8 as core::fmt::Display>::{vtable}", -0x40)];
+/// let remap = Remap::between(old_build, old, new);
+/// assert_eq!(remap.get("<u8 as core::fmt::Display>::{vtable}", 0x40), Some(-0x40));
+/// assert_eq!(remap.len(), 1);
+///
+/// let remap: Remap = bincode::deserialize(&bincode::serialize(&remap).unwrap()).unwrap();
+/// relative::register_remap(remap);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Remap {
+	build: Uuid,
+	offsets: BTreeMap<i64, BTreeMap<String, i64>>,
+}
+impl Remap {
+	/// Create an empty `Remap` for offsets serialized by the build `build`.
+	pub fn new(build: Uuid) -> Self {
+		Self {
+			build,
+			offsets: BTreeMap::new(),
+		}
+	}
+	/// Create a `Remap` for offsets serialized by the build `build`, from the
+	/// vtables of it and of this build, named by their
+	/// `<Concrete as Trait>::{vtable}` symbol along with their offsets from
+	/// the vtable of `RELATIVE_VTABLE_BASE` in each. Vtables that are only
+	/// present in one build are left out.
+	pub fn between<'a>(
+		build: Uuid, old: impl IntoIterator<Item = (&'a str, i64)>,
+		new: impl IntoIterator<Item = (&'a str, i64)>,
+	) -> Self {
+		let new: BTreeMap<_, _> = new.into_iter().collect();
+		let mut remap = Self::new(build);
+		for (name, old) in old {
+			if let Some(&new) = new.get(name) {
+				remap.insert(name, old, new);
+			}
+		}
+		remap
+	}
+	/// The build id of the build the offsets were serialized by.
+	pub fn build(&self) -> Uuid {
+		self.build
+	}
+	/// Translate the vtable named `name` at `old` to `new`.
+	pub fn insert(&mut self, name: &str, old: i64, new: i64) {
+		let _ = self
+			.offsets
+			.entry(old)
+			.or_default()
+			.insert(name.to_owned(), new);
+	}
+	/// The offset in this build that the vtable named `name` at `old`
+	/// translates to, if any.
+	pub fn get(&self, name: &str, old: i64) -> Option<i64> {
+		self.offsets.get(&old)?.get(name).copied()
+	}
+	/// The number of vtables translated.
+	pub fn len(&self) -> usize {
+		self.offsets.values().map(BTreeMap::len).sum()
+	}
+	/// Whether no vtables are translated.
+	pub fn is_empty(&self) -> bool {
+		self.offsets.is_empty()
+	}
+}
+impl Serialize for Remap {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let vtables: Vec<(&str, i64, i64)> = self
+			.offsets
+			.iter()
+			.flat_map(|(&old, names)| names.iter().map(move |(name, &new)| (&**name, old, new)))
+			.collect();
+		(&self.build, vtables).serialize(serializer)
+	}
+}
+impl<'de> Deserialize<'de> for Remap {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		<(Uuid, Vec<(String, i64, i64)>)>::deserialize(deserializer).map(|(build, vtables)| {
+			let mut remap = Self::new(build);
+			for (name, old, new) in vtables {
+				remap.insert(&name, old, new);
+			}
+			remap
+		})
+	}
+}
+
+/// Translate vtables serialized by the build of `remap` with it, rather than
+/// rejecting them with [`Error::BuildMismatch`].
+///
+/// A vtable from that build whose offset isn't in the table is rejected with
+/// [`Error::Unmapped`], and one whose offset is only in the table for vtables
+/// of other traits with [`Error::TypeMismatch`]. Registering a second table
+/// for the same build replaces the first.
+pub fn register_remap(remap: Remap) {
+	let _ = REMAPS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(remap.build, remap);
+}
+
+/// Whether a table is registered for `build`.
+pub(crate) fn known(build: Uuid) -> bool {
+	REMAPS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.contains_key(&build)
+}
+
+/// Translate the vtable for `T` at `offset`, serialized by `build`, with the
+/// table registered for it, or `None` if the table only translates vtables of
+/// other traits at that offset.
+pub(crate) fn translate<T: ?Sized + 'static>(
+	build: Uuid, offset: isize,
+) -> Result<Option<isize>, Error> {
+	let offset = offset as i64;
+	let remaps = REMAPS.lock().unwrap_or_else(PoisonError::into_inner);
+	let names = remaps
+		.get(&build)
+		.and_then(|remap| remap.offsets.get(&offset))
+		.ok_or(Error::Unmapped { build, offset })?;
+	names
+		.iter()
+		.find(|(name, _)| is_vtable_for(name, type_name::<T>()))
+		.map(|(_, &new)| isize::try_from(new).map_err(|_| Error::OffsetOutOfRange { offset: new }))
+		.transpose()
+}
+
+/// Whether `vtable`, named like `<u8 as core::fmt::Display>::{vtable}`, is for
+/// the trait object type named like `dyn core::fmt::Display + core::marker::Send`.
+pub fn is_vtable_for(vtable: &str, type_name: &str) -> bool {
+	let principal = type_name.trim_start_matches("dyn ");
+	let principal = principal.split(" + ").next().unwrap_or(principal);
+	vtable
+		.strip_suffix(">::{vtable}")
+		.is_some_and(|vtable| vtable.ends_with(&format!(" as {principal}")))
+}
@@ -0,0 +1,138 @@
+use serde::{
+	de::{Deserialize, Deserializer}, ser::{Serialize, Serializer}
+};
+use std::{
+	cell::Cell, collections::hash_map::DefaultHasher, hash::{Hash, Hasher}
+};
+use uuid::Uuid;
+
+use super::{func::RELATIVE_FUNC_BASE, type_id, vtable_base, Error, FORMAT_VERSION};
+
+thread_local! {
+	static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether relative references on this thread are currently being
+/// (de)serialized within a [`Session`].
+pub(crate) fn active() -> bool {
+	DEPTH.with(Cell::get) != 0
+}
+
+/// The identity of a binary, to be exchanged once between peers so that
+/// relative references can then be sent between them without the per-value
+/// build id and type id.
+///
+/// ```
+/// use relative::{vtable, BinaryIdentity, Vtable};
+/// use std::fmt::Display;
+///
+/// // exchange identities with the peer...
+/// let remote: BinaryIdentity =
+///     bincode::deserialize(&bincode::serialize(&BinaryIdentity::local()).unwrap()).unwrap();
+/// let session = remote.check().unwrap();
+///
+/// let a = vtable!(u8 => dyn Display);
+/// let bytes = session.scope(|| bincode::serialize(&a).unwrap());
+/// assert_eq!(bytes.len(), 8);
+/// let b: Vtable<dyn Display> = session.scope(|| bincode::deserialize(&bytes).unwrap());
+/// assert_eq!(a, b);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BinaryIdentity {
+	build: Uuid,
+	layout: u64,
+}
+impl BinaryIdentity {
+	/// The identity of this binary.
+	pub fn local() -> Self {
+		let mut hasher = DefaultHasher::new();
+		(
+			usize::BITS,
+			FORMAT_VERSION,
+			super::displacement(vtable_base(), RELATIVE_FUNC_BASE as usize),
+			type_id::<BinaryIdentity>(),
+		)
+			.hash(&mut hasher);
+		Self {
+			build: build_id::get(),
+			layout: hasher.finish(),
+		}
+	}
+	/// The build id of the binary.
+	pub fn build_id(&self) -> Uuid {
+		self.build
+	}
+	/// A fingerprint of the layout of the binary as loaded, covering the
+	/// pointer width, the format version and the position of the bases
+	/// relative to each other.
+	pub fn layout(&self) -> u64 {
+		self.layout
+	}
+	/// Check that a peer's identity matches this binary, returning a
+	/// [`Session`] in which relative references can be exchanged with it.
+	///
+	/// # Errors
+	///
+	/// [`Error::BuildMismatch`] if the peer is a different binary, or
+	/// [`Error::LayoutMismatch`] if it's the same binary but laid out
+	/// differently.
+	pub fn check(&self) -> Result<Session, Error> {
+		let local = Self::local();
+		if self.build != local.build {
+			return Err(Error::BuildMismatch {
+				got: self.build,
+				expected: local.build,
+			});
+		}
+		if self.layout != local.layout {
+			return Err(Error::LayoutMismatch {
+				got: self.layout,
+				expected: local.layout,
+			});
+		}
+		Ok(Session(()))
+	}
+}
+impl Serialize for BinaryIdentity {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		(self.build, self.layout).serialize(serializer)
+	}
+}
+impl<'de> Deserialize<'de> for BinaryIdentity {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		<(Uuid, u64)>::deserialize(deserializer).map(|(build, layout)| Self { build, layout })
+	}
+}
+
+/// A session with a peer whose [`BinaryIdentity`] has been checked.
+///
+/// Within [`Session::scope`], relative references serialize as just their
+/// offset, and deserialize without checking the build id or type id. Both
+/// peers must therefore serialize and deserialize them within a session, and
+/// the types they're deserialized as must match those they were serialized
+/// as, for example by being part of the same message schema.
+#[derive(Copy, Clone, Debug)]
+pub struct Session(());
+impl Session {
+	/// Run `f` with relative references on this thread (de)serialized in
+	/// session mode.
+	pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+		struct Guard;
+		impl Drop for Guard {
+			fn drop(&mut self) {
+				DEPTH.with(|depth| depth.set(depth.get() - 1));
+			}
+		}
+		DEPTH.with(|depth| depth.set(depth.get() + 1));
+		let _guard = Guard;
+		f()
+	}
+}
@@ -0,0 +1,439 @@
+use serde::{
+	de::{Deserialize, DeserializeOwned, Deserializer}, ser::{Serialize, Serializer}
+};
+use std::{any::type_name, cmp, fmt, hash, marker, mem::transmute_copy, ptr, slice, str};
+
+use super::{image, mac::Message, registry, Error, Offset, TraitObject, Vtable};
+
+/// Types that a [`Static`] can refer to.
+///
+/// This is implemented for all sized types, as well as `str` and `[T]`. It is
+/// sealed and cannot be implemented outside of this crate.
+pub trait Pointee: 'static + sealed::Sealed {
+	#[doc(hidden)]
+	type Metadata: Copy + Eq + Ord + hash::Hash + fmt::Debug + Serialize + DeserializeOwned;
+	#[doc(hidden)]
+	const ALIGN: usize;
+	#[doc(hidden)]
+	fn metadata(&self) -> Self::Metadata;
+	#[doc(hidden)]
+	fn from_raw_parts(data: *const (), metadata: Self::Metadata) -> *const Self;
+	#[doc(hidden)]
+	fn size(metadata: Self::Metadata) -> Option<usize>;
+}
+
+/// Types that a [`Static`] can be deserialized as: those for which any bytes
+/// are a valid value, as well as `str`, which is checked to be UTF-8.
+///
+/// This is implemented for the primitive integer and floating point types,
+/// `()`, `str`, and arrays and slices of these. It is sealed and cannot be
+/// implemented outside of this crate.
+pub trait Pod: Pointee + sealed::Pod {
+	#[doc(hidden)]
+	#[inline(always)]
+	fn valid(_bytes: &[u8]) -> bool {
+		true
+	}
+}
+
+mod sealed {
+	pub trait Sealed {}
+	pub trait Pod {}
+}
+
+macro_rules! pod {
+	($($t:ty),*) => {$(
+		impl sealed::Pod for $t {}
+		impl Pod for $t {}
+	)*};
+}
+pod!(
+	u8,
+	u16,
+	u32,
+	u64,
+	u128,
+	usize,
+	i8,
+	i16,
+	i32,
+	i64,
+	i128,
+	isize,
+	f32,
+	f64,
+	()
+);
+impl<T: Pod, const N: usize> sealed::Pod for [T; N] {}
+impl<T: Pod, const N: usize> Pod for [T; N] {}
+impl<T: Pod> sealed::Pod for [T] {}
+impl<T: Pod> Pod for [T] {}
+impl sealed::Pod for str {}
+impl Pod for str {
+	#[inline(always)]
+	fn valid(bytes: &[u8]) -> bool {
+		str::from_utf8(bytes).is_ok()
+	}
+}
+
+impl<T: 'static> sealed::Sealed for T {}
+impl<T: 'static> Pointee for T {
+	type Metadata = ();
+	const ALIGN: usize = align_of::<T>();
+	#[inline(always)]
+	fn metadata(&self) {}
+	#[inline(always)]
+	fn from_raw_parts(data: *const (), (): ()) -> *const Self {
+		data.cast()
+	}
+	#[inline(always)]
+	fn size((): ()) -> Option<usize> {
+		Some(size_of::<T>())
+	}
+}
+impl sealed::Sealed for str {}
+impl Pointee for str {
+	type Metadata = usize;
+	const ALIGN: usize = 1;
+	#[inline(always)]
+	fn metadata(&self) -> usize {
+		self.len()
+	}
+	#[inline(always)]
+	fn from_raw_parts(data: *const (), len: usize) -> *const Self {
+		ptr::slice_from_raw_parts(data.cast::<u8>(), len) as *const Self
+	}
+	#[inline(always)]
+	fn size(len: usize) -> Option<usize> {
+		Some(len)
+	}
+}
+impl<T: 'static> sealed::Sealed for [T] {}
+impl<T: 'static> Pointee for [T] {
+	type Metadata = usize;
+	const ALIGN: usize = align_of::<T>();
+	#[inline(always)]
+	fn metadata(&self) -> usize {
+		self.len()
+	}
+	#[inline(always)]
+	fn from_raw_parts(data: *const (), len: usize) -> *const Self {
+		ptr::slice_from_raw_parts(data.cast::<T>(), len)
+	}
+	#[inline(always)]
+	fn size(len: usize) -> Option<usize> {
+		len.checked_mul(size_of::<T>())
+	}
+}
+
+/// Wraps `&'static T` references to statics, string literals and
+/// const-promoted values such that they can be safely sent between other
+/// processes running the same binary.
+///
+/// The base used is the same as for [`Vtable`](crate::Vtable). References to
+/// zero-sized values aren't necessarily in static memory, and so are
+/// reconstructed as a dangling, well-aligned pointer.
+///
+/// It can only be deserialized, and [registered](crate::register_static), as a
+/// `Static<T>` where `T` is [`Pod`], as the bytes it refers to can't otherwise
+/// be checked to be a valid `T`.
+pub struct Static<T: ?Sized + Pointee>(
+	pub(crate) Option<isize>,
+	pub(crate) T::Metadata,
+	marker::PhantomData<&'static T>,
+);
+impl<T: ?Sized + Pointee> Static<T> {
+	#[inline(always)]
+	fn new(p: Option<isize>, metadata: T::Metadata) -> Self {
+		Self(p, metadata, marker::PhantomData)
+	}
+	/// Create a `Static<T>` from a `&'static T`.
+	///
+	/// # Safety
+	///
+	/// This is unsafe as it is up to the user to ensure the reference lies
+	/// within static memory, rather than e.g. being leaked from the heap.
+	///
+	/// i.e. the pointer needs to be positioned the same relative to the base in
+	/// every invocation, through e.g. being in the same segment, or the binary
+	/// being statically linked.
+	#[inline(always)]
+	pub unsafe fn from(ptr: &'static T) -> Self {
+		let offset = if size_of_val(ptr) != 0 {
+			let ptr: *const T = ptr;
+			Some(super::displacement(
+				super::vtable_base(),
+				ptr.cast::<()>() as usize,
+			))
+		} else {
+			None
+		};
+		Self::new(offset, ptr.metadata())
+	}
+	/// Get back a `&'static T` from a `Static<T>`.
+	#[inline(always)]
+	pub fn to(&self) -> &'static T {
+		let data = self.0.map_or(T::ALIGN, |offset| {
+			super::vtable_base().wrapping_add_signed(offset)
+		}) as *const ();
+		unsafe { &*T::from_raw_parts(data, self.1) }
+	}
+}
+impl<T: ?Sized + Pointee> Clone for Static<T> {
+	#[inline(always)]
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<T: ?Sized + Pointee> Copy for Static<T> {}
+impl<T: ?Sized + Pointee> PartialEq for Static<T> {
+	#[inline(always)]
+	fn eq(&self, other: &Self) -> bool {
+		(self.0, self.1) == (other.0, other.1)
+	}
+}
+impl<T: ?Sized + Pointee> Eq for Static<T> {}
+impl<T: ?Sized + Pointee> hash::Hash for Static<T> {
+	#[inline(always)]
+	fn hash<H: hash::Hasher>(&self, state: &mut H) {
+		(self.0, self.1).hash(state);
+	}
+}
+impl<T: ?Sized + Pointee> PartialOrd for Static<T> {
+	#[inline(always)]
+	fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl<T: ?Sized + Pointee> Ord for Static<T> {
+	#[inline(always)]
+	fn cmp(&self, other: &Self) -> cmp::Ordering {
+		(self.0, self.1).cmp(&(other.0, other.1))
+	}
+}
+impl<T: ?Sized + Pointee> fmt::Debug for Static<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		f.debug_struct("Static")
+			.field(type_name::<T>(), &(self.0, self.1))
+			.finish()
+	}
+}
+impl<T: ?Sized + Pointee> Serialize for Static<T> {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		super::serialize::<T, _, _>(&(self.0.map(Offset), self.1), message::<T>, serializer)
+	}
+}
+impl<'de, T: ?Sized + Pod> Deserialize<'de> for Static<T> {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let (offset, metadata): (Option<Offset>, _) =
+			super::deserialize::<T, _, _>(deserializer, message::<T>)?;
+		let offset = offset.map(|Offset(offset)| offset);
+		let size = T::size(metadata);
+		check_data(offset, size, T::ALIGN).map_err(Error::into_de)?;
+		if let (Some(offset), Some(size)) = (offset, size) {
+			registry::check_static::<T>(offset, size).map_err(Error::into_de)?;
+			let data = super::vtable_base().wrapping_add_signed(offset) as *const u8;
+			if !T::valid(unsafe { slice::from_raw_parts(data, size) }) {
+				return Err(Error::InvalidValue {
+					offset: offset as i64,
+					expected_name: type_name::<T>(),
+				}
+				.into_de());
+			}
+		}
+		Ok(Self::new(offset, metadata))
+	}
+}
+
+/// What a `Static<T>` is authenticated over: the offset and the size of the
+/// value referred to.
+fn message<T: ?Sized + Pointee>(
+	&(offset, metadata): &(Option<Offset>, T::Metadata), out: &mut Vec<u8>,
+) {
+	(offset, T::size(metadata)).write(out);
+}
+
+/// Check that a deserialized reference to `size` bytes lies within the loaded
+/// image, or has no offset if it's to a zero-sized value.
+fn check_data(offset: Option<isize>, size: Option<usize>, align: usize) -> Result<(), Error> {
+	match (offset, size) {
+		(None, Some(0)) => Ok(()),
+		(None, _) => Err(Error::OutsideImage { offset: None }),
+		(Some(offset), Some(size)) => image::check(offset, size, align, image::Kind::Any),
+		(Some(offset), None) => Err(Error::OutsideImage {
+			offset: Some(offset as i64),
+		}),
+	}
+}
+
+/// Wraps `&'static dyn Trait` references, where both the data and the vtable
+/// are in static memory, such that they can be safely sent between other
+/// processes running the same binary.
+///
+/// Construct one with the [`static_dyn`](crate::static_dyn) macro, or
+/// [`StaticDyn::from`].
+///
+/// As with [`Vtable`], the value is trusted to be of the concrete type its
+/// vtable is for, so getting it back is unsafe. Payloads from less trusted
+/// peers can be restricted to values that have been
+/// [registered](crate::register_static_dyn) by enabling
+/// [strict](crate::set_strict) mode.
+pub struct StaticDyn<T: ?Sized>(pub(crate) Option<isize>, pub(crate) Vtable<T>);
+impl<T: ?Sized> StaticDyn<T> {
+	/// Create a `StaticDyn<T>` from a `&'static T`.
+	///
+	/// # Safety
+	///
+	/// This is unsafe as it is up to the user to ensure that `T` is a trait
+	/// object type, and that the reference lies within static memory, rather
+	/// than e.g. being leaked from the heap.
+	///
+	/// i.e. the pointer needs to be positioned the same relative to the base in
+	/// every invocation, through e.g. being in the same segment, or the binary
+	/// being statically linked.
+	#[inline(always)]
+	pub unsafe fn from(ptr: &'static T) -> Self {
+		const { assert!(size_of::<&T>() == size_of::<TraitObject>()) };
+		let trait_object = transmute_copy::<&T, TraitObject>(&ptr);
+		let offset = if size_of_val(ptr) != 0 {
+			Some(super::displacement(
+				super::vtable_base(),
+				trait_object.data as usize,
+			))
+		} else {
+			None
+		};
+		Self(offset, Vtable::from(&*trait_object.vtable))
+	}
+	/// Get back a `&'static T` from a `StaticDyn<T>`.
+	///
+	/// # Safety
+	///
+	/// `self` must have been created by [`StaticDyn::from`] or the
+	/// [`static_dyn`](crate::static_dyn) macro, or deserialized from one that
+	/// was. Deserialization only checks that the value and its vtable lie
+	/// inside the loaded image, rather than that the value is of the concrete
+	/// type the vtable is for, so a `StaticDyn` from a less trusted peer must
+	/// have been [registered](crate::register_static_dyn) and deserialized in
+	/// [strict](crate::set_strict) mode.
+	#[inline(always)]
+	pub unsafe fn to(&self) -> &'static T {
+		let vtable = self.1.to();
+		let data = self.0.map_or_else(
+			|| super::vtable_align(vtable),
+			|offset| super::vtable_base().wrapping_add_signed(offset),
+		);
+		let trait_object = TraitObject {
+			data: data as *mut (),
+			vtable: ptr::from_ref(vtable).cast_mut(),
+		};
+		transmute_copy::<TraitObject, &'static T>(&trait_object)
+	}
+	/// Get the [`Vtable`] of a `StaticDyn<T>`.
+	#[inline(always)]
+	pub fn vtable(&self) -> Vtable<T> {
+		self.1
+	}
+}
+impl<T: ?Sized> Clone for StaticDyn<T> {
+	#[inline(always)]
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<T: ?Sized> Copy for StaticDyn<T> {}
+impl<T: ?Sized> PartialEq for StaticDyn<T> {
+	#[inline(always)]
+	fn eq(&self, other: &Self) -> bool {
+		(self.0, self.1) == (other.0, other.1)
+	}
+}
+impl<T: ?Sized> Eq for StaticDyn<T> {}
+impl<T: ?Sized> hash::Hash for StaticDyn<T> {
+	#[inline(always)]
+	fn hash<H: hash::Hasher>(&self, state: &mut H) {
+		(self.0, self.1).hash(state);
+	}
+}
+impl<T: ?Sized> PartialOrd for StaticDyn<T> {
+	#[inline(always)]
+	fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl<T: ?Sized> Ord for StaticDyn<T> {
+	#[inline(always)]
+	fn cmp(&self, other: &Self) -> cmp::Ordering {
+		(self.0, self.1).cmp(&(other.0, other.1))
+	}
+}
+impl<T: ?Sized> fmt::Debug for StaticDyn<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		f.debug_struct("StaticDyn")
+			.field(type_name::<T>(), &(self.0, (self.1).0))
+			.finish()
+	}
+}
+impl<T: ?Sized + 'static> Serialize for StaticDyn<T> {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		super::serialize::<T, _, _>(
+			&(self.0.map(Offset), Offset((self.1).0)),
+			Message::write,
+			serializer,
+		)
+	}
+}
+impl<'de, T: ?Sized + 'static> Deserialize<'de> for StaticDyn<T> {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let (offset, Offset(vtable)): (Option<Offset>, _) =
+			super::deserialize::<T, _, _>(deserializer, Message::write)?;
+		super::check_vtable::<T>(vtable).map_err(Error::into_de)?;
+		let offset = offset.map(|Offset(offset)| offset);
+		registry::check_static_dyn::<T>(offset, vtable).map_err(Error::into_de)?;
+		let vtable = Vtable::new(vtable);
+		check_data(
+			offset,
+			Some(super::vtable_size(vtable.to())),
+			super::vtable_align(vtable.to()),
+		)
+		.map_err(Error::into_de)?;
+		Ok(Self(offset, vtable))
+	}
+}
+
+/// Create a [`StaticDyn`] from a constant `&'static` reference, without
+/// requiring `unsafe`.
+///
+/// ```
+/// use relative::{static_dyn, StaticDyn};
+/// use std::fmt::Display;
+///
+/// let a: StaticDyn<dyn Display + Sync> = static_dyn!(&1234_u32 => dyn Display + Sync);
+/// assert_eq!(unsafe { a.to() }.to_string(), "1234");
+/// ```
+#[macro_export]
+macro_rules! static_dyn {
+	($value:expr => dyn $($bounds:tt)+) => {{
+		#[allow(unused_parens)]
+		const VALUE: &'static (dyn $($bounds)+) = $value;
+		#[allow(unused_unsafe)]
+		let static_dyn = unsafe { $crate::StaticDyn::<dyn $($bounds)+>::from(VALUE) };
+		static_dyn
+	}};
+}
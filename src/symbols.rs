@@ -0,0 +1,227 @@
+//! Lookup of the vtables in an ELF binary by name.
+//!
+//! Vtables don't have entries in the symbol table, but are described in the
+//! debug info by variables named like `<u8 as core::fmt::Display>::{vtable}`,
+//! so their names are only available when the binary was built with debug
+//! info. Other statics are looked up in the symbol table.
+
+use gimli::{AttributeValue, EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection, ObjectSymbol, RelocationTarget, SymbolKind};
+use std::{
+	any::type_name, borrow::Cow, collections::BTreeMap, env, fmt, fs, io, path::Path, sync::OnceLock
+};
+
+use super::{displacement, Vtable};
+
+pub use super::{remap::is_vtable_for, wire::Encoded};
+
+/// The vtables and symbols of an ELF binary, addressed by their offset from
+/// the vtable of `RELATIVE_VTABLE_BASE`, as they are by a
+/// [`Vtable`](crate::Vtable).
+///
+/// ```
+/// use relative::{symbols::{Encoded, Image}, vtable};
+/// use std::fmt::Display;
+///
+/// let a = bincode::serialize(&vtable!(u8 => dyn Display)).unwrap();
+/// let encoded: Encoded = bincode::deserialize(&a).unwrap();
+///
+/// let image = Image::open(std::env::current_exe().unwrap()).unwrap();
+/// // `None` if this binary wasn't built with debug info
+/// if let Some(vtable) = image.vtable(encoded.offset) {
+///     assert_eq!(vtable, "<u8 as core::fmt::Display>::{vtable}");
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Image {
+	base: u64,
+	vtables: BTreeMap<u64, String>,
+	symbols: BTreeMap<u64, (u64, String)>,
+}
+impl Image {
+	/// Read and parse the ELF binary at `path`.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be read or parsed, or doesn't contain
+	/// `RELATIVE_VTABLE_BASE`.
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		Self::parse(&fs::read(path)?)
+	}
+
+	/// The image of the running binary, parsed on first use, or `None` if it
+	/// couldn't be.
+	pub fn current() -> Option<&'static Self> {
+		static CURRENT: OnceLock<Option<Image>> = OnceLock::new();
+		CURRENT
+			.get_or_init(|| Self::open(env::current_exe().ok()?).ok())
+			.as_ref()
+	}
+
+	/// Parse an ELF binary.
+	///
+	/// # Errors
+	///
+	/// Fails if it can't be parsed, or doesn't contain `RELATIVE_VTABLE_BASE`.
+	pub fn parse(data: &[u8]) -> io::Result<Self> {
+		let file = object::File::parse(data).map_err(invalid)?;
+		let base = base(&file)?;
+		let mut symbols = BTreeMap::new();
+		for symbol in file.symbols() {
+			if matches!(symbol.kind(), SymbolKind::Data | SymbolKind::Text) && symbol.size() != 0 {
+				if let Ok(name) = symbol.name() {
+					let _ = symbols.insert(symbol.address(), (symbol.size(), name.to_owned()));
+				}
+			}
+		}
+		Ok(Self {
+			base,
+			vtables: vtables(&file).map_err(invalid)?,
+			symbols,
+		})
+	}
+
+	/// The address of the vtable of `RELATIVE_VTABLE_BASE`, that offsets are
+	/// taken from.
+	pub fn base(&self) -> u64 {
+		self.base
+	}
+
+	/// The name of the vtable at `offset` from the base, like
+	/// `<u8 as core::fmt::Display>::{vtable}`.
+	pub fn vtable(&self, offset: i64) -> Option<&str> {
+		self.vtables.get(&self.address(offset)).map(String::as_str)
+	}
+
+	/// The names of the vtables in the binary, with their offsets from the
+	/// base. A vtable can be duplicated across codegen units, so the same name
+	/// can appear with multiple offsets.
+	pub fn vtables(&self) -> impl Iterator<Item = (&str, i64)> + '_ {
+		self.vtables
+			.iter()
+			.map(move |(&address, name)| (name.as_str(), self.offset(address)))
+	}
+
+	/// The symbol containing the address at `offset` from the base, along with
+	/// the position of the address within it.
+	pub fn symbol(&self, offset: i64) -> Option<(&str, u64)> {
+		let address = self.address(offset);
+		let (&start, (size, name)) = self.symbols.range(..=address).next_back()?;
+		(address - start < *size).then_some((name.as_str(), address - start))
+	}
+
+	/// The offset of `address` from the base, as computed for a
+	/// [`Vtable`](crate::Vtable).
+	#[allow(clippy::cast_possible_truncation)]
+	fn offset(&self, address: u64) -> i64 {
+		displacement(self.base as usize, address as usize) as i64
+	}
+
+	#[allow(clippy::cast_sign_loss)]
+	fn address(&self, offset: i64) -> u64 {
+		self.base.wrapping_add(offset as u64)
+	}
+}
+
+fn invalid(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Find the vtable of `RELATIVE_VTABLE_BASE`, which is the second word of the
+/// trait object it holds. In position-independent binaries it's filled in by
+/// a relative relocation, whose addend is the address.
+fn base(file: &object::File) -> io::Result<u64> {
+	let symbol = file
+		.symbol_by_name("RELATIVE_VTABLE_BASE")
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "RELATIVE_VTABLE_BASE not found"))?;
+	let word = if file.is_64() { 8 } else { 4 };
+	let address = symbol.address() + word;
+	if let Some(relocations) = file.dynamic_relocations() {
+		for (offset, relocation) in relocations {
+			if offset == address
+				&& relocation.target() == RelocationTarget::Absolute
+				&& !relocation.has_implicit_addend()
+			{
+				#[allow(clippy::cast_sign_loss)]
+				return Ok(relocation.addend() as u64);
+			}
+		}
+	}
+	let data = file
+		.sections()
+		.find_map(|section| section.data_range(address, word).ok().flatten())
+		.ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				"RELATIVE_VTABLE_BASE has no data",
+			)
+		})?;
+	let mut bytes = [0; 8];
+	if file.is_little_endian() {
+		bytes[..data.len()].copy_from_slice(data);
+		Ok(u64::from_le_bytes(bytes))
+	} else {
+		bytes[8 - data.len()..].copy_from_slice(data);
+		Ok(u64::from_be_bytes(bytes))
+	}
+}
+
+/// The addresses and names of the `{vtable}` variables in the debug info.
+fn vtables(file: &object::File) -> Result<BTreeMap<u64, String>, gimli::Error> {
+	let endian = if file.is_little_endian() {
+		RunTimeEndian::Little
+	} else {
+		RunTimeEndian::Big
+	};
+	let sections = gimli::DwarfSections::load(|id| {
+		Ok::<_, gimli::Error>(
+			file.section_by_name(id.name())
+				.and_then(|section| section.uncompressed_data().ok())
+				.unwrap_or(Cow::Borrowed(&[])),
+		)
+	})?;
+	let dwarf = sections.borrow(|section| EndianSlice::new(section, endian));
+	let mut vtables = BTreeMap::new();
+	let mut units = dwarf.units();
+	while let Some(header) = units.next()? {
+		let unit = dwarf.unit(header)?;
+		let mut entries = unit.entries();
+		while let Some((_, entry)) = entries.next_dfs()? {
+			if entry.tag() != gimli::DW_TAG_variable {
+				continue;
+			}
+			let Some(name) = entry.attr_value(gimli::DW_AT_name)? else {
+				continue;
+			};
+			let name = dwarf.attr_string(&unit, name)?.to_string_lossy();
+			if !name.ends_with("::{vtable}") {
+				continue;
+			}
+			let Some(AttributeValue::Exprloc(expression)) =
+				entry.attr_value(gimli::DW_AT_location)?
+			else {
+				continue;
+			};
+			// Vtables removed by the linker are left at address 0.
+			if let Some(gimli::Operation::Address {
+				address: address @ 1..,
+			}) = expression.operations(unit.encoding()).next()?
+			{
+				let _ = vtables.insert(address, name.into_owned());
+			}
+		}
+	}
+	Ok(vtables)
+}
+
+/// A [`Vtable`] whose `Debug` form also includes its
+/// [symbol](Vtable::symbol), as returned by [`Vtable::with_symbol`].
+pub struct WithSymbol<T: ?Sized>(pub(crate) Vtable<T>);
+impl<T: ?Sized> fmt::Debug for WithSymbol<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		f.debug_struct("Vtable")
+			.field(type_name::<T>(), &(self.0).0)
+			.field("symbol", &self.0.symbol())
+			.finish()
+	}
+}
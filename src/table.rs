@@ -0,0 +1,171 @@
+use serde::{
+	de::{Deserialize, Deserializer}, ser::{Serialize, Serializer}
+};
+use std::{any::type_name, cell::RefCell, collections::HashMap, convert::TryFrom, mem};
+
+use super::{
+	deserialize, lookup_type_name, mac::Message, serialize, type_id, wire::Id, Error, Offset
+};
+
+thread_local! {
+	static SCOPE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+enum Scope {
+	Encode(VtableTable),
+	Decode(Vec<(u128, isize)>),
+}
+
+/// If encoding on this thread, intern the vtable for `T` at `offset` and
+/// return its index.
+pub(crate) fn intern<T: ?Sized + 'static>(offset: isize) -> Option<u32> {
+	SCOPE.with(|scope| match &mut *scope.borrow_mut() {
+		Some(Scope::Encode(table)) => Some(table.insert(type_id::<T>(), offset)),
+		_ => None,
+	})
+}
+
+/// Whether vtables on this thread are currently being decoded from indices.
+pub(crate) fn decoding() -> bool {
+	SCOPE.with(|scope| matches!(*scope.borrow(), Some(Scope::Decode(_))))
+}
+
+/// Resolve the index of a vtable for `T` against the table being decoded
+/// with.
+pub(crate) fn resolve<T: ?Sized + 'static>(index: u32) -> Result<isize, Error> {
+	SCOPE.with(|scope| {
+		let scope = scope.borrow();
+		let Some(Scope::Decode(entries)) = &*scope else {
+			unreachable!("not decoding")
+		};
+		let &(id, offset) = entries.get(index as usize).ok_or(Error::IndexOutOfRange {
+			index,
+			len: entries.len(),
+		})?;
+		if id != type_id::<T>() {
+			return Err(Error::TypeMismatch {
+				got: id,
+				got_name: lookup_type_name(id).map(String::from),
+				expected_name: type_name::<T>(),
+				expected_id: type_id::<T>(),
+			});
+		}
+		Ok(offset)
+	})
+}
+
+/// Interns the [`Vtable`](crate::Vtable)s of a batch of values, such that each
+/// distinct vtable is serialized once, and each value refers to it by index.
+///
+/// Values serialized within [`VtableTable::encode`] have their vtables
+/// collected into the table, which is then serialized, with the build id, and
+/// the type id of each vtable. The receiver deserializes the table and then
+/// the values within [`VtableTable::decode`], checking the type id of each
+/// vtable as it's resolved.
+///
+/// ```
+/// use relative::{vtable, Vtable, VtableTable};
+/// use std::fmt::Display;
+///
+/// let batch = vec![vtable!(u8 => dyn Display); 1000];
+///
+/// let mut table = VtableTable::new();
+/// let values = table.encode(|| bincode::serialize(&batch).unwrap());
+/// let table = bincode::serialize(&table).unwrap();
+/// // send `table` and `values` to remote...
+///
+/// let table: VtableTable = bincode::deserialize(&table).unwrap();
+/// assert_eq!(table.len(), 1);
+/// let values: Vec<Vtable<dyn Display>> =
+///     table.decode(|| bincode::deserialize(&values).unwrap());
+/// assert_eq!(values, batch);
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct VtableTable {
+	entries: Vec<(u128, isize)>,
+	index: HashMap<(u128, isize), u32>,
+}
+impl VtableTable {
+	/// Create an empty `VtableTable`.
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// The number of distinct vtables in the table.
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+	/// Whether the table is empty.
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+	fn insert(&mut self, id: u128, offset: isize) -> u32 {
+		let entries = &mut self.entries;
+		*self.index.entry((id, offset)).or_insert_with(|| {
+			let index = u32::try_from(entries.len()).expect("too many vtables");
+			entries.push((id, offset));
+			index
+		})
+	}
+	/// Run `f` with [`Vtable`](crate::Vtable)s serialized on this thread
+	/// interned into this table, and serialized as their index in it.
+	///
+	/// # Panics
+	///
+	/// Panics if more than `u32::MAX` distinct vtables are interned.
+	pub fn encode<R>(&mut self, f: impl FnOnce() -> R) -> R {
+		struct Guard<'a>(&'a mut VtableTable, Option<Scope>);
+		impl Drop for Guard<'_> {
+			fn drop(&mut self) {
+				if let Some(Scope::Encode(table)) = SCOPE.with(|scope| scope.replace(self.1.take()))
+				{
+					*self.0 = table;
+				}
+			}
+		}
+		let prev = SCOPE.with(|scope| scope.replace(Some(Scope::Encode(mem::take(self)))));
+		let _guard = Guard(self, prev);
+		f()
+	}
+	/// Run `f` with [`Vtable`](crate::Vtable)s deserialized on this thread
+	/// resolved from their index in this table.
+	pub fn decode<R>(&self, f: impl FnOnce() -> R) -> R {
+		struct Guard(Option<Scope>);
+		impl Drop for Guard {
+			fn drop(&mut self) {
+				let _ = SCOPE.with(|scope| scope.replace(self.0.take()));
+			}
+		}
+		let prev = SCOPE.with(|scope| scope.replace(Some(Scope::Decode(self.entries.clone()))));
+		let _guard = Guard(prev);
+		f()
+	}
+}
+impl Serialize for VtableTable {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let entries: Vec<(Id, Offset)> = self
+			.entries
+			.iter()
+			.map(|&(id, offset)| (Id(id), Offset(offset)))
+			.collect();
+		serialize::<Self, _, _>(&entries, Message::write, serializer)
+	}
+}
+impl<'de> Deserialize<'de> for VtableTable {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserialize::<Self, _, _>(deserializer, Message::write).map(|entries: Vec<(Id, Offset)>| {
+			let mut table = Self::new();
+			for (Id(id), Offset(offset)) in entries {
+				let _ = table.insert(id, offset);
+			}
+			table
+		})
+	}
+}
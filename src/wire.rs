@@ -0,0 +1,842 @@
+//! The serialized form of relative references.
+//!
+//! A relative reference is serialized as a tuple of:
+//!  * a format tag, which is the bytes `[FORMAT_VERSION, flags]`;
+//!  * the build id;
+//!  * the 128-bit id of the type, as 16 little-endian bytes, or 32 hex digits
+//!    with human-readable serializers;
+//!  * the name of the type, if the `TYPE_NAME` flag is set, which it is with
+//!    the "type-names" feature;
+//!  * the name a vtable is registered under, if the `NAMED` flag is set,
+//!    which it is in [named](crate::set_named) mode, along with `TYPE_NAME`;
+//!  * a payload, such as an offset;
+//!  * a MAC over the build id, type id, flags, type name, registered name and
+//!    payload, if the `MAC` flag is set, which it is when a key has been set
+//!    with the "mac" feature.
+//!
+//! The format tag distinguishes it from the 0.2 layout of
+//! `(build_id, type_id, offset)`, where the type id was a hashed `u64`, which
+//! is still accepted. Flags not known to the deserializer, or a format version
+//! other than [`FORMAT_VERSION`], are rejected with
+//! [`Error::UnsupportedFormat`]; tagged versions start at 1, as 0.2 was
+//! untagged.
+//!
+//! A [`Vtable`](crate::Vtable) is instead serialized as a string
+//! `"<build-id>:<type-id>:<offset>"` by human-readable serializers, with the
+//! type id and offset in hex. The MAC, if any, is appended in hex as a fourth
+//! part. As it has no room for names, the tuple is used instead when the
+//! `TYPE_NAME` or `NAMED` flag would be set, and is always accepted.
+//!
+//! A vtable from a different build is translated rather than rejected if it
+//! was serialized with a registered name, or if a [`Remap`](crate::Remap) is
+//! registered for that build, which applies to the 0.2 layout too.
+//!
+//! Within a [`Session`](crate::Session), only the payload is serialized, along
+//! with the MAC if a key has been set.
+//! Within [`VtableTable::encode`](crate::VtableTable::encode), a `Vtable` is
+//! serialized as a `u32` index into the table.
+
+use serde::{
+	de::{self, Deserialize, Deserializer, SeqAccess, Visitor}, ser::{Serialize, SerializeTuple, Serializer}
+};
+use std::{any::type_name, convert::TryFrom, fmt, marker, str};
+use uuid::Uuid;
+
+use super::{
+	legacy_type_id, lookup_type_name, mac::{self, Mac, Message}, registry, remap, session, table, type_id, Error
+};
+
+/// The version of the format that relative references are serialized in.
+///
+/// This is incremented whenever the serialized form changes. Deserialization
+/// accepts this version and the untagged layout used by relative 0.2, which
+/// preceded it.
+pub const FORMAT_VERSION: u8 = 1;
+
+const TYPE_NAME: u8 = 1 << 0;
+const MAC: u8 = 1 << 1;
+const NAMED: u8 = 1 << 2;
+const KNOWN_FLAGS: u8 = TYPE_NAME | MAC | NAMED;
+
+const FLAGS: u8 = if cfg!(feature = "type-names") {
+	TYPE_NAME
+} else {
+	0
+};
+
+/// The first element of the serialized tuple. Distinguishing it from the
+/// `Uuid` that began the 0.2 layout relies on a `Uuid` being serialized as 16
+/// bytes, or a string in human-readable formats.
+enum Tag {
+	Legacy(Uuid),
+	Version(u8, u8),
+}
+impl Serialize for Tag {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		match self {
+			Self::Legacy(build) => build.serialize(serializer),
+			Self::Version(version, flags) => serializer.serialize_bytes(&[*version, *flags]),
+		}
+	}
+}
+impl<'de> Deserialize<'de> for Tag {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		if deserializer.is_human_readable() {
+			deserializer.deserialize_any(TagVisitor)
+		} else {
+			deserializer.deserialize_bytes(TagVisitor)
+		}
+	}
+}
+struct TagVisitor;
+impl<'de> Visitor<'de> for TagVisitor {
+	type Value = Tag;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a relative format tag")
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		Uuid::parse_str(v).map(Tag::Legacy).map_err(E::custom)
+	}
+
+	fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		match v {
+			[version, flags] => Ok(Tag::Version(*version, *flags)),
+			_ => Uuid::from_slice(v).map(Tag::Legacy).map_err(E::custom),
+		}
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut bytes = Vec::with_capacity(16);
+		while let Some(byte) = seq.next_element()? {
+			bytes.push(byte);
+		}
+		self.visit_bytes(&bytes)
+	}
+}
+
+/// Serialize `payload` alongside the build id and the id of `T`, as well as
+/// the name of `T` with the "type-names" feature, or alone within a session.
+/// If a MAC key is set, it's authenticated over the bytes written by
+/// `message`.
+#[inline]
+pub(crate) fn serialize<T: ?Sized + 'static, P: Serialize, S: Serializer>(
+	payload: &P, message: impl FnOnce(&P, &mut Vec<u8>), serializer: S,
+) -> Result<S::Ok, S::Error> {
+	serialize_named::<T, P, S>(payload, message, None, serializer)
+}
+
+/// As [`serialize`], additionally with the name `payload` is registered under
+/// and the name of `T`, if any, outside of a session.
+fn serialize_named<T: ?Sized + 'static, P: Serialize, S: Serializer>(
+	payload: &P, message: impl FnOnce(&P, &mut Vec<u8>), name: Option<&str>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+	if session::active() {
+		let mac = mac::sign(build_id::get(), type_id::<T>(), |out| message(payload, out));
+		return match mac {
+			Some(mac) => (payload, mac).serialize(serializer),
+			None => payload.serialize(serializer),
+		};
+	}
+	let flags = FLAGS
+		| if mac::active() { MAC } else { 0 }
+		| if name.is_some() { NAMED | TYPE_NAME } else { 0 };
+	let type_name = (flags & TYPE_NAME != 0).then(type_name::<T>);
+	let mac = mac::sign(
+		build_id::get(),
+		type_id::<T>(),
+		header(flags, type_name, name, |out| message(payload, out)),
+	);
+	let len = 4
+		+ usize::from(flags & TYPE_NAME != 0)
+		+ usize::from(name.is_some())
+		+ usize::from(mac.is_some());
+	let mut tuple = serializer.serialize_tuple(len)?;
+	tuple.serialize_element(&Tag::Version(FORMAT_VERSION, flags))?;
+	tuple.serialize_element(&build_id::get())?;
+	tuple.serialize_element(&Id(type_id::<T>()))?;
+	if let Some(type_name) = type_name {
+		tuple.serialize_element(type_name)?;
+	}
+	if let Some(name) = name {
+		tuple.serialize_element(name)?;
+	}
+	tuple.serialize_element(payload)?;
+	if let Some(mac) = &mac {
+		tuple.serialize_element(mac)?;
+	}
+	tuple.end()
+}
+
+/// Prefix the bytes written by `message` with the flags and names a reference
+/// is serialized with, such that they're authenticated along with its payload.
+fn header<'a>(
+	flags: u8, type_name: Option<&'a str>, registered: Option<&'a str>,
+	message: impl FnOnce(&mut Vec<u8>) + 'a,
+) -> impl FnOnce(&mut Vec<u8>) + 'a {
+	move |out| {
+		(flags, (type_name, registered)).write(out);
+		message(out);
+	}
+}
+
+/// Deserialize a payload, checking that it came from this binary and that it
+/// was serialized as a `T`, or alone within a session. If a MAC key is set,
+/// it's checked to be authenticated over the bytes written by `message`.
+#[inline]
+pub(crate) fn deserialize<'de, T: ?Sized + 'static, P: Deserialize<'de>, D: Deserializer<'de>>(
+	deserializer: D, message: impl FnOnce(&P, &mut Vec<u8>),
+) -> Result<P, D::Error> {
+	if session::active() {
+		let (payload, mac) = if mac::active() {
+			<(P, Mac)>::deserialize(deserializer).map(|(payload, mac)| (payload, Some(mac)))?
+		} else {
+			(P::deserialize(deserializer)?, None)
+		};
+		mac::verify(
+			build_id::get(),
+			type_id::<T>(),
+			|out| message(&payload, out),
+			mac,
+		)
+		.map_err(Error::into_de)?;
+		return Ok(payload);
+	}
+	deserializer.deserialize_tuple(7, RelativeVisitor::<T, P, _>::new(message, None, None))
+}
+
+/// Translates a payload serialized by a different build, given its build id,
+/// the id and name of its type, and the name it was registered under.
+type Foreign<P> = fn(Uuid, u128, Option<String>, Option<&str>, &P) -> Result<P, Error>;
+
+/// Converts the `u64` a payload was serialized as in the 0.2 layout.
+type Legacy<P> = fn(u64) -> Result<P, Error>;
+
+/// Deserializes the tuple, translating a payload serialized by a different
+/// build using the second field, if any, rather than rejecting it, and reading
+/// the payload of the 0.2 layout using the third field, if any.
+struct RelativeVisitor<T: ?Sized, P, F>(
+	F,
+	Option<Foreign<P>>,
+	Option<Legacy<P>>,
+	marker::PhantomData<fn(T) -> P>,
+);
+impl<T: ?Sized, P, F> RelativeVisitor<T, P, F> {
+	fn new(message: F, foreign: Option<Foreign<P>>, legacy: Option<Legacy<P>>) -> Self {
+		Self(message, foreign, legacy, marker::PhantomData)
+	}
+}
+impl<'de, T: ?Sized + 'static, P: Deserialize<'de>, F: FnOnce(&P, &mut Vec<u8>)> Visitor<'de>
+	for RelativeVisitor<T, P, F>
+{
+	type Value = P;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a relative reference to {}", type_name::<T>())
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut index = 0;
+		match next(&mut seq, &mut index, &self)? {
+			Tag::Legacy(build) => {
+				let id: u64 = next(&mut seq, &mut index, &self)?;
+				let payload = match self.2 {
+					Some(legacy) => {
+						legacy(next(&mut seq, &mut index, &self)?).map_err(Error::into_de)?
+					}
+					None => next(&mut seq, &mut index, &self)?,
+				};
+				let translated = match self.1 {
+					Some(foreign) if build != build_id::get() => {
+						foreign(build, u128::from(id), None, None, &payload)
+							.map_err(Error::into_de)?
+					}
+					_ => {
+						check::<T>(
+							build,
+							u128::from(id),
+							u128::from(legacy_type_id::<T>()),
+							None,
+						)
+						.map_err(Error::into_de)?;
+						payload
+					}
+				};
+				// the 0.2 layout has no MAC, so this only passes if no key is set
+				mac::verify(build, u128::from(id), |_| (), None).map_err(Error::into_de)?;
+				Ok(translated)
+			}
+			Tag::Version(FORMAT_VERSION, flags) if flags & !KNOWN_FLAGS == 0 => {
+				let build = next(&mut seq, &mut index, &self)?;
+				let Id(id) = next(&mut seq, &mut index, &self)?;
+				let name: Option<String> = if flags & TYPE_NAME != 0 {
+					Some(next(&mut seq, &mut index, &self)?)
+				} else {
+					None
+				};
+				let registered: Option<String> = if flags & NAMED != 0 {
+					Some(next(&mut seq, &mut index, &self)?)
+				} else {
+					None
+				};
+				let payload = next(&mut seq, &mut index, &self)?;
+				let mac = if flags & MAC != 0 {
+					Some(next(&mut seq, &mut index, &self)?)
+				} else {
+					None
+				};
+				let Self(write, foreign, ..) = self;
+				let message = header(flags, name.as_deref(), registered.as_deref(), |out| {
+					write(&payload, out);
+				});
+				match foreign {
+					Some(foreign) if build != build_id::get() => {
+						let translated =
+							foreign(build, id, name.clone(), registered.as_deref(), &payload)
+								.map_err(Error::into_de)?;
+						mac::verify(build, id, message, mac).map_err(Error::into_de)?;
+						Ok(translated)
+					}
+					_ => {
+						check::<T>(build, id, type_id::<T>(), name.clone())
+							.map_err(Error::into_de)?;
+						mac::verify(build, id, message, mac).map_err(Error::into_de)?;
+						Ok(payload)
+					}
+				}
+			}
+			Tag::Version(version, flags) => {
+				Err(Error::UnsupportedFormat { version, flags }.into_de())
+			}
+		}
+	}
+}
+
+/// The 128-bit id of a type, serialized as 16 little-endian bytes, or as 32
+/// hex digits by human-readable serializers, many of which don't support
+/// integers that wide.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Id(pub(crate) u128);
+impl Serialize for Id {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		if serializer.is_human_readable() {
+			serializer.collect_str(&format_args!("{:032x}", self.0))
+		} else {
+			self.0.to_le_bytes().serialize(serializer)
+		}
+	}
+}
+impl<'de> Deserialize<'de> for Id {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		if deserializer.is_human_readable() {
+			deserializer.deserialize_str(IdVisitor)
+		} else {
+			deserializer.deserialize_tuple(16, IdVisitor)
+		}
+	}
+}
+struct IdVisitor;
+impl<'de> Visitor<'de> for IdVisitor {
+	type Value = Id;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a 128-bit type id")
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		if v.len() != 32 {
+			return Err(E::invalid_length(v.len(), &self));
+		}
+		u128::from_str_radix(v, 16).map(Id).map_err(E::custom)
+	}
+
+	fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		<[u8; 16]>::try_from(v)
+			.map(|bytes| Id(u128::from_le_bytes(bytes)))
+			.map_err(|_| E::invalid_length(v.len(), &self))
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut bytes = [0; 16];
+		for (i, byte) in bytes.iter_mut().enumerate() {
+			*byte = seq
+				.next_element()?
+				.ok_or_else(|| de::Error::invalid_length(i, &self))?;
+		}
+		Ok(Id(u128::from_le_bytes(bytes)))
+	}
+}
+
+/// A signed displacement from a base, serialized as an `i64` regardless of
+/// pointer width.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Offset(pub(crate) isize);
+impl Serialize for Offset {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_i64(self.0 as i64)
+	}
+}
+impl<'de> Deserialize<'de> for Offset {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserializer.deserialize_i64(OffsetVisitor)
+	}
+}
+struct OffsetVisitor;
+impl Visitor<'_> for OffsetVisitor {
+	type Value = Offset;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a relative offset")
+	}
+
+	fn visit_i64<E>(self, offset: i64) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		isize::try_from(offset)
+			.map(Offset)
+			.map_err(|_| Error::OffsetOutOfRange { offset }.into_de())
+	}
+
+	fn visit_u64<E>(self, offset: u64) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		i64::try_from(offset)
+			.map_err(|_| E::invalid_value(de::Unexpected::Unsigned(offset), &self))
+			.and_then(|offset| self.visit_i64(offset))
+	}
+}
+
+/// Offsets were serialized as a `usize` in the 0.2 layout, which is always
+/// written as a `u64`, so reinterpret it as the two's complement displacement
+/// it was wrapped from at the pointer width it was serialized at.
+#[allow(clippy::cast_possible_wrap)]
+fn legacy_offset(offset: u64) -> Result<Offset, Error> {
+	usize::try_from(offset)
+		.map(|offset| Offset(offset as isize))
+		.map_err(|_| Error::OffsetOutOfRange {
+			offset: offset as i64,
+		})
+}
+
+/// Serialize an offset from a base, as an index when encoding a
+/// [`VtableTable`](crate::VtableTable), as a string with human-readable
+/// serializers outside of a session when there are no names to include, and
+/// as the tuple otherwise.
+#[inline]
+pub(crate) fn serialize_offset<T: ?Sized + 'static, S: Serializer>(
+	offset: Offset, serializer: S,
+) -> Result<S::Ok, S::Error> {
+	if let Some(index) = table::intern::<T>(offset.0) {
+		return serializer.serialize_u32(index);
+	}
+	let name = registry::name::<T>(offset.0);
+	if serializer.is_human_readable()
+		&& !session::active()
+		&& name.is_none()
+		&& FLAGS & TYPE_NAME == 0
+	{
+		serializer.collect_str(&Display::<T>(offset, marker::PhantomData))
+	} else {
+		serialize_named::<T, _, _>(&offset, Message::write, name, serializer)
+	}
+}
+
+/// Deserialize an offset from a base, as an index when decoding a
+/// [`VtableTable`](crate::VtableTable), accepting the string form from
+/// human-readable deserializers outside of a session.
+#[inline]
+pub(crate) fn deserialize_offset<'de, T: ?Sized + 'static, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Offset, D::Error> {
+	if table::decoding() {
+		let index = u32::deserialize(deserializer)?;
+		return table::resolve::<T>(index)
+			.map(Offset)
+			.map_err(Error::into_de);
+	}
+	if deserializer.is_human_readable() && !session::active() {
+		deserializer.deserialize_any(StrOrTupleVisitor::<T>(marker::PhantomData))
+	} else if session::active() {
+		deserialize::<T, _, _>(deserializer, Message::write)
+	} else {
+		deserializer.deserialize_tuple(
+			7,
+			RelativeVisitor::<T, _, _>::new(Offset::write, Some(foreign::<T>), Some(legacy_offset)),
+		)
+	}
+}
+
+/// Translate an offset serialized by a different build, by the name it was
+/// registered under in named mode, or otherwise with the remapping table
+/// registered for that build.
+fn foreign<T: ?Sized + 'static>(
+	build: Uuid, id: u128, name: Option<String>, registered: Option<&str>, offset: &Offset,
+) -> Result<Offset, Error> {
+	if registered.is_none() && !remap::known(build) {
+		return Err(Error::BuildMismatch {
+			got: build,
+			expected: build_id::get(),
+		});
+	}
+	// Type ids aren't stable between builds, so compare the name of the type
+	// where it was serialized with it, and otherwise rely on the remapping
+	// table, which only translates a vtable as the trait it's named for.
+	let mismatch = |name| Error::TypeMismatch {
+		got: id,
+		got_name: name,
+		expected_name: type_name::<T>(),
+		expected_id: type_id::<T>(),
+	};
+	if name.as_deref().is_some_and(|name| name != type_name::<T>()) {
+		return Err(mismatch(name));
+	}
+	match registered {
+		Some(registered) => registry::resolve::<T>(registered),
+		None => remap::translate::<T>(build, offset.0)?.ok_or_else(|| mismatch(name)),
+	}
+	.map(Offset)
+}
+
+struct StrOrTupleVisitor<T: ?Sized>(marker::PhantomData<fn(T)>);
+impl<'de, T: ?Sized + 'static> Visitor<'de> for StrOrTupleVisitor<T> {
+	type Value = Offset;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a relative reference to {}", type_name::<T>())
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		from_str::<T>(v).map_err(Error::into_de)
+	}
+
+	fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		RelativeVisitor::<T, _, _>::new(Offset::write, Some(foreign::<T>), Some(legacy_offset))
+			.visit_seq(seq)
+	}
+}
+
+/// Formats an offset from a base as `"<build-id>:<type-id>:<offset>"`,
+/// followed by `":<mac>"` if a MAC key is set.
+pub(crate) struct Display<T: ?Sized>(pub(crate) Offset, pub(crate) marker::PhantomData<fn(T)>);
+impl<T: ?Sized + 'static> fmt::Display for Display<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let Offset(offset) = self.0;
+		write!(f, "{}:{:x}:", build_id::get(), type_id::<T>())?;
+		if offset < 0 {
+			write!(f, "-{:x}", offset.unsigned_abs())?;
+		} else {
+			write!(f, "{offset:x}")?;
+		}
+		let message = header(MAC, None, None, |out| self.0.write(out));
+		if let Some(Mac(mac)) = mac::sign(build_id::get(), type_id::<T>(), message) {
+			f.write_str(":")?;
+			for byte in mac {
+				write!(f, "{byte:02x}")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Parse an offset from a base from `"<build-id>:<type-id>:<offset>"`,
+/// optionally followed by `":<mac>"`, checking that it came from this binary,
+/// or one with a registered remapping table, that it was formatted as a `T`
+/// and, if a MAC key is set, that it's authenticated.
+pub(crate) fn from_str<T: ?Sized + 'static>(s: &str) -> Result<Offset, Error> {
+	let (build, id, offset, mac) = parse(s)?;
+	let local = build == build_id::get();
+	if local {
+		check::<T>(build, id, type_id::<T>(), None)?;
+	}
+	let offset = isize::try_from(offset)
+		.map(Offset)
+		.map_err(|_| Error::OffsetOutOfRange { offset })?;
+	let translated = if local {
+		offset
+	} else {
+		foreign::<T>(build, id, None, None, &offset)?
+	};
+	let flags = if mac.is_some() { MAC } else { 0 };
+	mac::verify(
+		build,
+		id,
+		header(flags, None, None, |out| offset.write(out)),
+		mac,
+	)?;
+	Ok(translated)
+}
+
+/// Split `"<build-id>:<type-id>:<offset>[:<mac>]"` into its parts.
+fn parse(s: &str) -> Result<(Uuid, u128, i64, Option<Mac>), Error> {
+	let malformed = || Error::Malformed {
+		input: s.to_owned(),
+	};
+	let mut parts = s.splitn(4, ':');
+	let (Some(build), Some(id), Some(offset)) = (parts.next(), parts.next(), parts.next()) else {
+		return Err(malformed());
+	};
+	let build = Uuid::parse_str(build).map_err(|_| malformed())?;
+	let id = u128::from_str_radix(id, 16).map_err(|_| malformed())?;
+	let offset = i64::from_str_radix(offset, 16).map_err(|_| malformed())?;
+	let mac = parts
+		.next()
+		.map(|mac| {
+			let mut bytes = [0; 32];
+			if mac.len() != 64 || !mac.is_ascii() {
+				return Err(malformed());
+			}
+			for (byte, hex) in bytes.iter_mut().zip(mac.as_bytes().chunks(2)) {
+				let hex = str::from_utf8(hex).map_err(|_| malformed())?;
+				*byte = u8::from_str_radix(hex, 16).map_err(|_| malformed())?;
+			}
+			Ok(Mac(bytes))
+		})
+		.transpose()?;
+	Ok((build, id, offset, mac))
+}
+
+/// A serialized [`Vtable`](crate::Vtable) of any type, decoded without being
+/// checked against this binary, for inspection.
+///
+/// It's decoded from the tuple, in the current or 0.2 layout, or the string
+/// form, but not from within a [`Session`](crate::Session) or
+/// [`VtableTable`](crate::VtableTable), where that information isn't
+/// serialized alongside each vtable.
+#[cfg(any(test, feature = "symbols"))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Encoded {
+	/// The format version, or `None` for the 0.2 layout.
+	pub version: Option<u8>,
+	/// The id of the build it was serialized by.
+	pub build: Uuid,
+	/// The id of the trait object type, which was a hashed `u64` in the 0.2
+	/// layout.
+	pub type_id: u128,
+	/// The name of the trait object type, if it was serialized with it.
+	pub type_name: Option<String>,
+	/// The name the vtable was registered under, if it was serialized in
+	/// [named](crate::set_named) mode.
+	pub name: Option<String>,
+	/// The offset of the vtable from the base.
+	pub offset: i64,
+	/// The MAC, if it was serialized with one.
+	pub mac: Option<[u8; 32]>,
+}
+/// Serializes it as the tuple, with the flags implied by which of the optional
+/// fields are present, such that an altered `Encoded` can be used to check
+/// how a tampered reference is handled.
+#[cfg(any(test, feature = "symbols"))]
+impl Serialize for Encoded {
+	#[allow(clippy::cast_sign_loss)]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let Some(version) = self.version else {
+			let type_id = u64::try_from(self.type_id)
+				.map_err(|_| serde::ser::Error::custom("type id too wide for the 0.2 layout"))?;
+			return (Tag::Legacy(self.build), type_id, self.offset as u64).serialize(serializer);
+		};
+		let flags = if self.type_name.is_some() {
+			TYPE_NAME
+		} else {
+			0
+		} | if self.name.is_some() { NAMED } else { 0 }
+			| if self.mac.is_some() { MAC } else { 0 };
+		let len = 4
+			+ usize::from(self.type_name.is_some())
+			+ usize::from(self.name.is_some())
+			+ usize::from(self.mac.is_some());
+		let mut tuple = serializer.serialize_tuple(len)?;
+		tuple.serialize_element(&Tag::Version(version, flags))?;
+		tuple.serialize_element(&self.build)?;
+		tuple.serialize_element(&Id(self.type_id))?;
+		if let Some(type_name) = &self.type_name {
+			tuple.serialize_element(type_name)?;
+		}
+		if let Some(name) = &self.name {
+			tuple.serialize_element(name)?;
+		}
+		tuple.serialize_element(&self.offset)?;
+		if let Some(mac) = self.mac {
+			tuple.serialize_element(&Mac(mac))?;
+		}
+		tuple.end()
+	}
+}
+#[cfg(any(test, feature = "symbols"))]
+impl<'de> Deserialize<'de> for Encoded {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		if deserializer.is_human_readable() {
+			deserializer.deserialize_any(EncodedVisitor)
+		} else {
+			deserializer.deserialize_tuple(7, EncodedVisitor)
+		}
+	}
+}
+#[cfg(any(test, feature = "symbols"))]
+struct EncodedVisitor;
+#[cfg(any(test, feature = "symbols"))]
+impl<'de> Visitor<'de> for EncodedVisitor {
+	type Value = Encoded;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a relative reference to a vtable")
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		let (build, type_id, offset, mac) = parse(v).map_err(Error::into_de)?;
+		Ok(Encoded {
+			version: Some(FORMAT_VERSION),
+			build,
+			type_id,
+			type_name: None,
+			name: None,
+			offset,
+			mac: mac.map(|Mac(mac)| mac),
+		})
+	}
+
+	fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+	where
+		A: SeqAccess<'de>,
+	{
+		let mut index = 0;
+		match next(&mut seq, &mut index, &self)? {
+			Tag::Legacy(build) => {
+				let type_id: u64 = next(&mut seq, &mut index, &self)?;
+				let Offset(offset) =
+					legacy_offset(next(&mut seq, &mut index, &self)?).map_err(Error::into_de)?;
+				Ok(Encoded {
+					version: None,
+					build,
+					type_id: u128::from(type_id),
+					type_name: None,
+					name: None,
+					offset: offset as i64,
+					mac: None,
+				})
+			}
+			Tag::Version(FORMAT_VERSION, flags) if flags & !KNOWN_FLAGS == 0 => {
+				let build = next(&mut seq, &mut index, &self)?;
+				let Id(type_id) = next(&mut seq, &mut index, &self)?;
+				let type_name = if flags & TYPE_NAME != 0 {
+					Some(next(&mut seq, &mut index, &self)?)
+				} else {
+					None
+				};
+				let name = if flags & NAMED != 0 {
+					Some(next(&mut seq, &mut index, &self)?)
+				} else {
+					None
+				};
+				let Offset(offset) = next(&mut seq, &mut index, &self)?;
+				let mac = if flags & MAC != 0 {
+					let Mac(mac) = next(&mut seq, &mut index, &self)?;
+					Some(mac)
+				} else {
+					None
+				};
+				Ok(Encoded {
+					version: Some(FORMAT_VERSION),
+					build,
+					type_id,
+					type_name,
+					name,
+					offset: offset as i64,
+					mac,
+				})
+			}
+			Tag::Version(version, flags) => {
+				Err(Error::UnsupportedFormat { version, flags }.into_de())
+			}
+		}
+	}
+}
+
+fn next<'de, A: SeqAccess<'de>, E: Deserialize<'de>>(
+	seq: &mut A, index: &mut usize, expected: &dyn de::Expected,
+) -> Result<E, A::Error> {
+	*index += 1;
+	seq.next_element()?
+		.ok_or_else(|| de::Error::invalid_length(*index - 1, expected))
+}
+
+fn check<T: ?Sized + 'static>(
+	build: Uuid, id: u128, expected_id: u128, name: Option<String>,
+) -> Result<(), Error> {
+	let local = build_id::get();
+	if build != local {
+		return Err(Error::BuildMismatch {
+			got: build,
+			expected: local,
+		});
+	}
+	if id != expected_id {
+		return Err(Error::TypeMismatch {
+			got: id,
+			got_name: name.or_else(|| lookup_type_name(id).map(String::from)),
+			expected_name: type_name::<T>(),
+			expected_id,
+		});
+	}
+	Ok(())
+}